@@ -2,7 +2,6 @@ import { ChangingRoom } from "../utils/models.ts";
 
 interface EditRoomProps {
   room: ChangingRoom;
-  apiUrl: string;
 }
 
 export default function EditRoom(props: EditRoomProps) {
@@ -13,15 +12,13 @@ export default function EditRoom(props: EditRoomProps) {
       return;
     }
 
-    const res = await fetch(`${props.apiUrl}/rooms/${props.room.id}`, {
-      method: "PUT",
+    const res = await fetch(`/rooms/${props.room.id}`, {
+      method: "PATCH",
       headers: {
-        "Content-Type": "application/json",
+        "Content-Type": "application/merge-patch+json",
+        "If-Match": `"${props.room.revision}"`,
       },
-      body: JSON.stringify({
-        ...props.room,
-        name: new_name,
-      }),
+      body: JSON.stringify({ name: new_name }),
     });
 
     if (res.ok) {
@@ -37,8 +34,11 @@ export default function EditRoom(props: EditRoomProps) {
 
   const handleDelete = async () => {
     if (confirm("Sikker?")) {
-      const res = await fetch(`${props.apiUrl}/rooms/${props.room.id}`, {
+      const res = await fetch(`/rooms/${props.room.id}`, {
         method: "DELETE",
+        headers: {
+          "If-Match": `"${props.room.revision}"`,
+        },
       });
       if (res.ok) {
         console.info("Room deleted successfully. Redirecting home.");
@@ -36,7 +36,7 @@ export const handler: Handlers<NewReviewData> = {
     });
   },
   async POST(req, ctx) {
-    const { isSignedIn, userName } = await getSignedInUser(req);
+    const { isSignedIn, userName, accessToken } = await getSignedInUser(req);
 
     const formData = await req.formData();
     const roomId = formData.get("roomId")?.valueOf() as string;
@@ -69,11 +69,16 @@ export const handler: Handlers<NewReviewData> = {
       reviewedBy = undefined;
     }
 
+    const reqHeaders: Record<string, string> = {
+      "Content-Type": "application/json",
+    };
+    if (accessToken) {
+      reqHeaders["Authorization"] = `Bearer ${accessToken}`;
+    }
+
     const res = await fetch(`${reviewApiUrl}/reviews`, {
       method: "POST",
-      headers: {
-        "Content-Type": "application/json",
-      },
+      headers: reqHeaders,
       body: JSON.stringify({
         roomId,
         availabilityRating,
@@ -179,16 +184,20 @@ function renderForm(data: NewReviewData) {
         id="reviewText"
         rows={3}
       />
-      <label class="block text-md font-bold" for="reviewedBy">
-        Ditt navn (frivillig)
-      </label>
-      <input
-        class="shadow border rounded w-full mb-3"
-        type="text"
-        name="reviewedBy"
-        id="reviewedBy"
-        title="Skriv gjerne inn ditt fornavn / kallenavn el. lignende"
-      />
+      {!data.isSignedIn && (
+        <>
+          <label class="block text-md font-bold" for="reviewedBy">
+            Ditt navn (frivillig)
+          </label>
+          <input
+            class="shadow border rounded w-full mb-3"
+            type="text"
+            name="reviewedBy"
+            id="reviewedBy"
+            title="Skriv gjerne inn ditt fornavn / kallenavn el. lignende"
+          />
+        </>
+      )}
 
       <button
         class="shadow bg-gray-300 text-md font-bold rounded p-2 w-full"
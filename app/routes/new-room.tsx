@@ -40,7 +40,7 @@ export const handler: Handlers<NewRoomData> = {
     });
   },
   async POST(req, ctx) {
-    const { isSignedIn, userName } = await getSignedInUser(req);
+    const { isSignedIn, userName, accessToken } = await getSignedInUser(req);
 
     const formData = await req.formData();
     const lat = parseFloat(formData.get("lat")?.valueOf() as string);
@@ -49,6 +49,7 @@ export const handler: Handlers<NewRoomData> = {
       method: "POST",
       headers: {
         "Content-Type": "application/json",
+        "Authorization": `Bearer ${accessToken}`,
       },
       body: JSON.stringify({
         name: formData.get("name"),
@@ -38,8 +38,47 @@ export const handler: Handlers<RoomData> = {
 
     return ctx.render({ isSignedIn, userName, room, reviews });
   },
+  PATCH(req, ctx) {
+    return forwardToRoomApi(req, ctx.params.id);
+  },
+  DELETE(req, ctx) {
+    return forwardToRoomApi(req, ctx.params.id);
+  },
 };
 
+// Changes from the EditRoom island are sent through here,
+// so the access token stays on the server
+async function forwardToRoomApi(req: Request, id: string): Promise<Response> {
+  const { accessToken } = await getSignedInUser(req);
+  if (!accessToken) {
+    return new Response("Du må logge inn for å endre stellerom", {
+      status: 401,
+    });
+  }
+
+  const headers: Record<string, string> = {
+    "Authorization": `Bearer ${accessToken}`,
+  };
+  for (const name of ["Content-Type", "If-Match"]) {
+    const value = req.headers.get(name);
+    if (value) {
+      headers[name] = value;
+    }
+  }
+
+  const res = await fetch(`${roomApiUrl}/rooms/${id}`, {
+    method: req.method,
+    headers,
+    body: req.method === "DELETE" ? undefined : await req.text(),
+  });
+
+  if (!res.ok) {
+    console.error(`${res.status} ${res.statusText} error from room api`);
+  }
+
+  return new Response(await res.text(), { status: res.status });
+}
+
 export default function Room(
   { data }: PageProps<RoomData>,
 ) {
@@ -117,7 +156,7 @@ export default function Room(
         </a>
         {renderReviews()}
         <h3 class="text-md font-bold">Administrer stellerom</h3>
-        <EditRoom room={room} apiUrl={roomApiUrl} />
+        <EditRoom room={room} />
       </main>
     </div>
   );
@@ -2,21 +2,63 @@ export type StarRating = 1 | 2 | 3 | 4 | 5;
 
 export interface ChangingRoom {
   id: string;
+  revision: number;
   name: string | undefined | null;
   location: { lat: number; lng: number };
   ratings: {
     availability: StarRating;
     safety: StarRating;
     cleanliness: StarRating;
+    count?: number | null;
+    averages?: {
+      availability: number;
+      safety: number;
+      cleanliness: number;
+    } | null;
+    recentAverages?: {
+      availability: number;
+      safety: number;
+      cleanliness: number;
+    } | null;
+    histograms?: {
+      availability: number[];
+      safety: number[];
+      cleanliness: number[];
+    } | null;
+    score?: number | null;
+    recentScore?: number | null;
+    lastReviewedAt?: string | null;
   } | null;
+  attributes?: Attributes;
+  osmTags?: Record<string, string> | null;
+  hidden?: boolean;
+}
+
+export type Placement = "genderNeutral" | "womens" | "mens";
+
+export interface Attributes {
+  wheelchair: boolean | null;
+  placement: Placement[];
+  fee: boolean | null;
+  openingHours: string | null;
+  indoor: boolean | null;
+  level: string | null;
+  sink: boolean | null;
+  nursingArea: boolean | null;
+  diapersForSale: boolean | null;
+  operator: string | null;
 }
 
 export interface Review {
+  id: string;
   roomId: string;
   availabilityRating: StarRating;
   safetyRating: StarRating;
   cleanlinessRating: StarRating;
   review: string | null | undefined;
   reviewedBy: string;
+  reviewerId?: string | null;
+  imageId?: string | null;
+  status: "pending" | "approved" | "rejected";
   reviewedAt: Date;
 }
@@ -1,17 +1,51 @@
 use std::env;
 
 use geojson::{Geometry, Value};
-use models::{ChangingRoom, Location};
+use models::{ChangeSource, ChangingRoom, Location, RoomChange, RoomHistoryEntry};
 use mongodb::bson::{doc, Uuid};
 use mongodb::IndexModel;
-use mongodb::{options::ClientOptions, Client, Collection, Database};
+use mongodb::{options::ClientOptions, Client, ClientSession, Collection, Database};
 use osmgraph::api::{OverpassResponse, QueryEngine};
 use osmgraph::graph::{get_osm_nodes, OSMNode};
+use tags::{attributes_from_tags, merge_attributes};
 
 mod models; // models module symlinked from room-api
+mod tags;
+
+const ACTOR: &str = "osm-sync";
+
+/// Starts a transaction for changing a room together with recording the change,
+/// so that a change is never saved without its history entry
+async fn start_transaction(
+    collection: &Collection<ChangingRoom>,
+) -> Result<ClientSession, mongodb::error::Error> {
+    let mut session = collection.client().start_session().await?;
+    session.start_transaction().await?;
+    Ok(session)
+}
+
+async fn record_change(
+    history: &Collection<RoomHistoryEntry>,
+    session: &mut ClientSession,
+    action: RoomChange,
+    before: Option<ChangingRoom>,
+    after: ChangingRoom,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entry = RoomHistoryEntry::new(
+        action,
+        ChangeSource::OsmSync,
+        ACTOR.to_owned(),
+        None,
+        before,
+        after,
+    );
+    history.insert_one(&entry).session(session).await?;
+    Ok(())
+}
 
 async fn update_changing_room(
     collection: &Collection<ChangingRoom>,
+    history: &Collection<RoomHistoryEntry>,
     existing_doc: &ChangingRoom,
     node: &OSMNode,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -31,42 +65,99 @@ async fn update_changing_room(
             }
         });
 
+    let external_id = Some(format!("osm:{}", node.id()));
+    let location = Location {
+        lat: node.lat(),
+        lng: node.lon(),
+    };
+    let osm_tags = node.tags().clone();
+    let attributes = match &osm_tags {
+        Some(tags) => merge_attributes(&existing_doc.attributes, attributes_from_tags(tags)),
+        None => existing_doc.attributes.clone(),
+    };
+
+    if name == existing_doc.name
+        && external_id == existing_doc.external_id
+        && location == existing_doc.location
+        && attributes == existing_doc.attributes
+        && osm_tags == existing_doc.osm_tags
+    {
+        tracing::info!("Room {} is up to date", existing_doc.id);
+        return Ok(());
+    }
+
     let updated = ChangingRoom {
         id: existing_doc.id,
-        external_id: Some(format!("osm:{}", node.id())),
+        revision: existing_doc.revision + 1,
+        external_id,
         name,
-        location: Location {
-            lat: node.lat(),
-            lng: node.lon(),
-        },
+        location,
         location_geo: Geometry::new(Value::Point(vec![node.lon(), node.lat()])),
         ratings: existing_doc.ratings.clone(),
+        attributes,
+        osm_tags,
+        deleted: existing_doc.deleted.clone(),
+        hidden: existing_doc.hidden,
     };
 
-    collection
-        .find_one_and_replace(doc! {"id": existing_doc.id}, updated)
+    let mut session = start_transaction(collection).await?;
+    let replaced = collection
+        .find_one_and_replace(
+            doc! {"id": existing_doc.id, "revision": existing_doc.revision},
+            &updated,
+        )
+        .session(&mut session)
         .await?;
+
+    if replaced.is_none() {
+        tracing::warn!(
+            "Room {} was modified during sync. Skipping update",
+            existing_doc.id
+        );
+        session.abort_transaction().await?;
+        return Ok(());
+    }
+
+    record_change(
+        history,
+        &mut session,
+        RoomChange::Update,
+        Some(existing_doc.clone()),
+        updated,
+    )
+    .await?;
+    session.commit_transaction().await?;
     Ok(())
 }
 
 async fn upsert_changing_room(
     collection: &Collection<ChangingRoom>,
+    history: &Collection<RoomHistoryEntry>,
     node: &OSMNode,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if let Some(room) = collection
         .find_one(doc! {"externalId": &format!("osm:{}", node.id())})
         .await?
     {
+        if room.deleted.is_some() {
+            tracing::info!(
+                "Skipping deleted room {} {:?}, identified by node id {}",
+                room.id,
+                room.external_id,
+                node.id(),
+            );
+            return Ok(());
+        }
         tracing::info!(
             "Updating existing room {} {:?}, identified by node id {}",
             room.id,
             room.external_id,
             node.id(),
         );
-        update_changing_room(collection, &room, node).await?;
+        update_changing_room(collection, history, &room, node).await?;
     } else if let Some(room) = collection
         .find_one(doc! {
-        "location_geo": doc! {
+        "locationGeo": doc! {
             "$near": {
                 "$geometry": {
                     "type": "Point", "coordinates": [node.lon(), node.lat()]
@@ -76,6 +167,16 @@ async fn upsert_changing_room(
         }})
         .await?
     {
+        if room.deleted.is_some() {
+            tracing::info!(
+                "Skipping deleted room {} {:?} identified by geo proximity {:?} {:?}",
+                room.id,
+                room.external_id,
+                room.location,
+                (node.lon(), node.lat()),
+            );
+            return Ok(());
+        }
         tracing::info!(
             "Updating existing room {} {:?} identified by geo proximity {:?} {:?}",
             room.id,
@@ -83,7 +184,7 @@ async fn upsert_changing_room(
             room.location,
             (node.lon(), node.lat()),
         );
-        update_changing_room(collection, &room, node).await?;
+        update_changing_room(collection, history, &room, node).await?;
     } else {
         tracing::info!(
             "Adding new room for node {} {:?}",
@@ -98,6 +199,7 @@ async fn upsert_changing_room(
 
         let room = ChangingRoom {
             id: Uuid::new(),
+            revision: 1,
             external_id: Some(format!("osm:{}", node.id())),
             name,
             location: Location {
@@ -106,9 +208,20 @@ async fn upsert_changing_room(
             },
             location_geo: Geometry::new(Value::Point(vec![node.lon(), node.lat()])),
             ratings: None,
+            attributes: node
+                .tags()
+                .as_ref()
+                .map(attributes_from_tags)
+                .unwrap_or_default(),
+            osm_tags: node.tags().clone(),
+            deleted: None,
+            hidden: false,
         };
 
-        collection.insert_one(&room).await?;
+        let mut session = start_transaction(collection).await?;
+        collection.insert_one(&room).session(&mut session).await?;
+        record_change(history, &mut session, RoomChange::Create, None, room).await?;
+        session.commit_transaction().await?;
     }
     Ok(())
 }
@@ -117,7 +230,7 @@ async fn ensure_db_ix(
     collection: &Collection<ChangingRoom>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let ix = IndexModel::builder()
-        .keys(doc! { "location_geo": "2dsphere"})
+        .keys(doc! { "locationGeo": "2dsphere"})
         .build();
 
     let ix = collection.create_index(ix).await?;
@@ -159,6 +272,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let db = get_db_handle().await?;
     let collection = db.collection::<ChangingRoom>("rooms");
+    let history = db.collection::<RoomHistoryEntry>("room_history");
 
     let engine = QueryEngine::new();
 
@@ -178,7 +292,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ensure_db_ix(&collection).await?;
 
     for n in nodes {
-        upsert_changing_room(&collection, &n).await?;
+        upsert_changing_room(&collection, &history, &n).await?;
     }
 
     Ok(())
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use crate::models::{Attributes, Placement};
+
+/// Translates the tags of an OpenStreetMap changing table node into room attributes.
+///
+/// See <https://wiki.openstreetmap.org/wiki/Key:changing_table>.
+/// `changing_table:*` tags describe the changing table itself,
+/// so they take precedence over the general tags which usually describe the surrounding amenity.
+pub fn attributes_from_tags(tags: &HashMap<String, String>) -> Attributes {
+    let tag = |key: &str| {
+        tags.get(&format!("changing_table:{key}"))
+            .or_else(|| tags.get(key))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut placement = Vec::new();
+    for location in tag("location").unwrap_or_default().split(';') {
+        let p = match location.trim() {
+            "female_toilet" => Placement::Womens,
+            "male_toilet" => Placement::Mens,
+            "unisex_toilet" | "wheelchair_toilet" | "dedicated_room" | "room" => {
+                Placement::GenderNeutral
+            }
+            _ => continue,
+        };
+        if !placement.contains(&p) {
+            placement.push(p);
+        }
+    }
+
+    Attributes {
+        wheelchair: tag("wheelchair").and_then(yes_no),
+        placement,
+        fee: tag("fee").and_then(yes_no),
+        opening_hours: tag("opening_hours").map(String::from),
+        level: tag("level").map(String::from),
+        operator: tag("operator").map(String::from),
+        ..Default::default()
+    }
+}
+
+/// Combines attributes from OpenStreetMap with the existing ones.
+///
+/// Values from OpenStreetMap win, but attributes it has no information about are kept,
+/// since they might have been added by our users.
+pub fn merge_attributes(existing: &Attributes, from_osm: Attributes) -> Attributes {
+    Attributes {
+        wheelchair: from_osm.wheelchair.or(existing.wheelchair),
+        placement: if from_osm.placement.is_empty() {
+            existing.placement.clone()
+        } else {
+            from_osm.placement
+        },
+        fee: from_osm.fee.or(existing.fee),
+        opening_hours: from_osm
+            .opening_hours
+            .or_else(|| existing.opening_hours.clone()),
+        indoor: from_osm.indoor.or(existing.indoor),
+        level: from_osm.level.or_else(|| existing.level.clone()),
+        sink: from_osm.sink.or(existing.sink),
+        nursing_area: from_osm.nursing_area.or(existing.nursing_area),
+        diapers_for_sale: from_osm.diapers_for_sale.or(existing.diapers_for_sale),
+        operator: from_osm.operator.or_else(|| existing.operator.clone()),
+    }
+}
+
+fn yes_no(value: &str) -> Option<bool> {
+    match value {
+        "yes" | "designated" => Some(true),
+        "no" => Some(false),
+        // e.g. "limited" for wheelchair access, which we can't tell parents much about
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn yes_no_values() {
+        let cases = [
+            ("yes", Some(true)),
+            ("designated", Some(true)),
+            ("no", Some(false)),
+            ("limited", None),
+            ("unknown", None),
+            ("", None),
+        ];
+
+        for (value, expected) in cases {
+            let attributes = attributes_from_tags(&tags(&[("wheelchair", value), ("fee", value)]));
+            assert_eq!(attributes.wheelchair, expected, "wheelchair={value}");
+            assert_eq!(attributes.fee, expected, "fee={value}");
+        }
+    }
+
+    #[test]
+    fn placement_from_location() {
+        let cases: [(&str, &[Placement]); 6] = [
+            (
+                "female_toilet;male_toilet",
+                &[Placement::Womens, Placement::Mens],
+            ),
+            (
+                "female_toilet; male_toilet",
+                &[Placement::Womens, Placement::Mens],
+            ),
+            ("unisex_toilet", &[Placement::GenderNeutral]),
+            (
+                "wheelchair_toilet;dedicated_room",
+                &[Placement::GenderNeutral],
+            ),
+            ("female_toilet;sales_area", &[Placement::Womens]),
+            ("sales_area", &[]),
+        ];
+
+        for (location, expected) in cases {
+            let attributes = attributes_from_tags(&tags(&[("changing_table:location", location)]));
+            assert_eq!(attributes.placement, expected, "location={location}");
+        }
+    }
+
+    #[test]
+    fn changing_table_tags_take_precedence() {
+        let attributes = attributes_from_tags(&tags(&[
+            ("fee", "yes"),
+            ("changing_table:fee", "no"),
+            ("wheelchair", "limited"),
+            ("opening_hours", "Mo-Fr 08:00-16:00"),
+            ("operator", " Oslo kommune "),
+            ("level", ""),
+        ]));
+
+        assert_eq!(attributes.fee, Some(false));
+        assert_eq!(attributes.wheelchair, None);
+        assert_eq!(
+            attributes.opening_hours.as_deref(),
+            Some("Mo-Fr 08:00-16:00")
+        );
+        assert_eq!(attributes.operator.as_deref(), Some("Oslo kommune"));
+        assert_eq!(attributes.level, None);
+    }
+
+    #[test]
+    fn merge_keeps_attributes_unknown_to_osm() {
+        let existing = Attributes {
+            wheelchair: Some(true),
+            placement: vec![Placement::GenderNeutral],
+            fee: Some(true),
+            sink: Some(true),
+            level: Some("1".to_owned()),
+            ..Default::default()
+        };
+        let from_osm = attributes_from_tags(&tags(&[
+            ("fee", "no"),
+            ("wheelchair", "limited"),
+            ("changing_table:location", "female_toilet"),
+        ]));
+
+        let merged = merge_attributes(&existing, from_osm);
+        assert_eq!(merged.fee, Some(false));
+        assert_eq!(merged.wheelchair, Some(true));
+        assert_eq!(merged.placement, [Placement::Womens]);
+        assert_eq!(merged.sink, Some(true));
+        assert_eq!(merged.level.as_deref(), Some("1"));
+    }
+}
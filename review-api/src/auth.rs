@@ -0,0 +1,105 @@
+//! Bearer token authentication for users, with anonymous requests allowed where the handler takes `Option<User>`.
+//!
+//! Token validation itself lives in jwt.rs, which is a symlink to room-api/src/jwt.rs.
+
+use std::{env, sync::Arc};
+
+use axum::{
+    extract::{FromRef, FromRequestParts, OptionalFromRequestParts},
+    http::{header, request::Parts, StatusCode},
+};
+
+use crate::jwt::TokenValidator;
+
+/// Authenticates users by their bearer tokens
+#[derive(Debug)]
+pub struct Authenticator {
+    tokens: TokenValidator,
+    admin_role: String,
+    moderator_role: String,
+}
+
+/// A user authenticated with a valid bearer token
+#[derive(Clone, Debug)]
+pub struct User {
+    pub subject: String,
+    pub name: Option<String>,
+    pub is_admin: bool,
+    /// Can approve and reject reviews. Admins are moderators as well
+    pub is_moderator: bool,
+}
+
+impl Authenticator {
+    /// Configures authentication from `REVIEW_API_*` env vars. See [`TokenValidator::from_env`].
+    pub async fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Authenticator {
+            tokens: TokenValidator::from_env("REVIEW_API").await?,
+            admin_role: env::var("REVIEW_API_ADMIN_ROLE").unwrap_or("admin".to_owned()),
+            moderator_role: env::var("REVIEW_API_MODERATOR_ROLE").unwrap_or("moderator".to_owned()),
+        })
+    }
+
+    pub async fn authenticate(&self, token: &str) -> Result<User, String> {
+        let claims = self.tokens.validate(token).await?;
+        let is_admin = claims.has_role(&self.admin_role);
+        let is_moderator = is_admin || claims.has_role(&self.moderator_role);
+
+        Ok(User {
+            subject: claims.subject,
+            name: claims.name,
+            is_admin,
+            is_moderator,
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for User
+where
+    Arc<Authenticator>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                "Missing bearer token in Authorization header".to_owned(),
+            ))?;
+
+        let authenticator = Arc::<Authenticator>::from_ref(state);
+        authenticator.authenticate(token).await.map_err(|e| {
+            tracing::warn!(err = e, "Rejected bearer token");
+            (
+                StatusCode::UNAUTHORIZED,
+                format!("Invalid bearer token. {e}"),
+            )
+        })
+    }
+}
+
+/// Requests without an Authorization header are anonymous, but invalid tokens are still rejected
+impl<S> OptionalFromRequestParts<S> for User
+where
+    Arc<Authenticator>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        if !parts.headers.contains_key(header::AUTHORIZATION) {
+            return Ok(None);
+        }
+
+        <User as FromRequestParts<S>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
+    }
+}
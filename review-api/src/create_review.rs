@@ -1,19 +1,34 @@
 use axum::{extract::State, http::StatusCode, Json};
 use chrono::Utc;
-use futures::TryStreamExt;
 use mongodb::{
     bson::{doc, Uuid},
-    Collection, Database,
+    Database,
 };
-use reqwest::Client;
 use serde::Deserialize;
-use serde_json::json;
-use std::{env, sync::LazyLock};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::auth::User;
+use crate::models::{Image, ModerationStatus, Review, StarRating};
+use crate::outbox::{enqueue_ratings_changed, start_transaction};
+use crate::ratings::ROOM_API_CLIENT;
 
-use crate::models::{Review, StarRating};
+/// Rooms are only looked up in room-api again after this long, as they are rarely deleted
+const VERIFIED_ROOM_TTL: Duration = Duration::from_secs(60 * 60);
 
-static ALLOWED_IMAGE_BASE_URLS: LazyLock<Vec<String>> = LazyLock::new(|| {
-    serde_json::from_str(&env::var("ALLOWED_IMAGE_BASE_URLS").unwrap_or("[]".to_owned())).unwrap()
+/// When rooms were last verified to exist in room-api
+static VERIFIED_ROOMS: LazyLock<Mutex<HashMap<Uuid, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub static ALLOW_ANONYMOUS_REVIEWS: LazyLock<bool> = LazyLock::new(|| {
+    env::var("REVIEW_API_ALLOW_ANONYMOUS")
+        .map(|v| v != "false")
+        .unwrap_or(true)
 });
 
 #[derive(Clone, Debug, Deserialize)]
@@ -27,136 +42,236 @@ pub struct CreateReview {
     #[serde(rename = "cleanlinessRating")]
     pub cleanliness_rating: StarRating,
     pub review: Option<String>,
-    #[serde(rename = "imageUrl")]
-    pub image_url: Option<String>,
+    /// Id of an image uploaded with `POST /images`
+    #[serde(rename = "imageId")]
+    pub image_id: Option<Uuid>,
+    /// Ignored for signed in users, whose name is taken from their token
     #[serde(rename = "reviewedBy")]
     pub reviewed_by: Option<String>,
 }
 
 pub async fn create_review(
+    user: Option<User>,
     State(db): State<Database>,
     Json(payload): Json<CreateReview>,
 ) -> Result<(StatusCode, Json<Review>), (StatusCode, String)> {
-    validate_payload(&payload)?;
+    if user.is_none() && !*ALLOW_ANONYMOUS_REVIEWS {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Anonymous reviews are not allowed. Please sign in".to_owned(),
+        ));
+    }
+
+    validate_room_id(&db, &payload.room_id).await?;
+    validate_image_id(&db, user.as_ref(), payload.image_id).await?;
+
+    let (reviewed_by, reviewer_id) = match user {
+        Some(user) => (user.name, Some(user.subject)),
+        None => (payload.reviewed_by, None),
+    };
 
     let collection = db.collection::<Review>("reviews");
 
+    let status = initial_status(payload.review.as_deref(), payload.image_id);
+
     let review = Review {
+        id: Uuid::new(),
         room_id: payload.room_id,
         availability_rating: payload.availability_rating,
         safety_rating: payload.safety_rating,
         cleanliness_rating: payload.cleanliness_rating,
         review: payload.review,
-        image_url: payload.image_url,
-        reviewed_by: payload.reviewed_by,
+        image_url: None,
+        image_id: payload.image_id,
+        reviewed_by,
+        reviewer_id,
         reviewed_at: Utc::now(),
+        status,
+        moderation: None,
     };
 
-    collection.insert_one(&review).await.map_err(|e| {
+    let persist_error = |e: mongodb::error::Error| {
         tracing::error!(err = e.to_string(), "Error persisting review to db");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "An unexpected error occured trying to persist review to database".to_owned(),
         )
-    })?;
+    };
 
-    update_room_ratings(&collection, &payload.room_id)
+    // The ratings are sent to room-api in the background, so the review is saved even if room-api is down
+    let mut session = start_transaction(&db).await.map_err(persist_error)?;
+    collection
+        .insert_one(&review)
+        .session(&mut session)
         .await
-        .map_err(|e| {
-            tracing::error!(
-                err = e.to_string(),
-                "Error updating ratings in room service"
-            );
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "An unexpected error occured trying to update ratings for room".to_owned(),
-            )
-        })?;
+        .map_err(persist_error)?;
+
+    if status == ModerationStatus::Approved {
+        enqueue_ratings_changed(&db, &mut session, review.room_id)
+            .await
+            .map_err(persist_error)?;
+    } else {
+        tracing::info!(
+            review_id = review.id.to_string(),
+            "Review awaits moderation"
+        );
+    }
+
+    session.commit_transaction().await.map_err(persist_error)?;
 
     Ok((StatusCode::CREATED, Json(review)))
 }
 
-fn validate_payload(payload: &CreateReview) -> Result<(), (StatusCode, String)> {
-    if payload.image_url.is_some()
-        && ALLOWED_IMAGE_BASE_URLS
-            .iter()
-            .any(|allowed| !payload.image_url.clone().unwrap().starts_with(allowed))
+/// Checks with room-api that the room being reviewed exists and is visible, so typos don't create orphan reviews.
+/// Rooms which have been verified recently, or already have reviews when room-api is unavailable, are accepted
+async fn validate_room_id(db: &Database, room_id: &Uuid) -> Result<(), (StatusCode, String)> {
+    if VERIFIED_ROOMS
+        .lock()
+        .unwrap()
+        .get(room_id)
+        .is_some_and(|verified| verified.elapsed() < VERIFIED_ROOM_TTL)
     {
-        tracing::error!(
-            url = payload.image_url,
-            "Validation error: Illegal image URL"
-        );
-        Err((
+        return Ok(());
+    }
+
+    let unavailable = || {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Unable to verify room. Please try again later".to_owned(),
+        )
+    };
+
+    let room = match get_room(room_id).await {
+        Ok(room) => room,
+        Err(e) => {
+            tracing::error!(err = e.to_string(), "Unable to get room from room-api");
+
+            // Reviews are only created for rooms which were verified at the time
+            let reviewed = db
+                .collection::<Review>("reviews")
+                .find_one(doc! { "roomId": room_id })
+                .await
+                .map_err(|e| {
+                    tracing::error!(err = e.to_string(), "Unable to get reviews of room");
+                    unavailable()
+                })?;
+            return match reviewed {
+                Some(_) => Ok(()),
+                None => Err(unavailable()),
+            };
+        }
+    };
+
+    match room {
+        RoomLookup::Found { hidden: true } => Err((
             StatusCode::UNPROCESSABLE_ENTITY,
-            format!(
-                "Invalid image url. URL's must start with {:?}",
-                ALLOWED_IMAGE_BASE_URLS.join(",")
-            ),
-        ))
-    } else {
-        Ok(())
+            format!("Room with id {room_id} is hidden while reports about it are checked"),
+        )),
+        RoomLookup::Found { hidden: false } => {
+            VERIFIED_ROOMS
+                .lock()
+                .unwrap()
+                .insert(*room_id, Instant::now());
+            Ok(())
+        }
+        RoomLookup::NotFound => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("No room found with id {room_id}"),
+        )),
+        RoomLookup::Deleted => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Room with id {room_id} has been deleted"),
+        )),
     }
 }
 
-async fn update_room_ratings(
-    collection: &Collection<Review>,
-    room_id: &Uuid,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let reviews = collection
-        .find(doc! { "roomId": *room_id })
-        .await?
-        .try_collect::<Vec<_>>()
-        .await?;
+enum RoomLookup {
+    Found { hidden: bool },
+    NotFound,
+    Deleted,
+}
 
-    let availability = (reviews
-        .iter()
-        .map(|r| u32::from(r.availability_rating))
-        .sum::<u32>() as f64
-        / reviews.len() as f64)
-        .round() as u8;
-
-    let safety = (reviews
-        .iter()
-        .map(|r| u32::from(r.safety_rating))
-        .sum::<u32>() as f64
-        / reviews.len() as f64)
-        .round() as u8;
-
-    let cleanliness = (reviews
-        .iter()
-        .map(|r| u32::from(r.cleanliness_rating))
-        .sum::<u32>() as f64
-        / reviews.len() as f64)
-        .round() as u8;
-
-    let url = format!(
-        "{base_url}/rooms/{room_id}",
-        base_url = env::var("ROOM_API_URL")?
-    );
-
-    let client = Client::new();
-
-    let mut get_room_res = client
-        .get(&url)
+/// Looks up a room in room-api
+async fn get_room(room_id: &Uuid) -> Result<RoomLookup, Box<dyn std::error::Error + Send + Sync>> {
+    let res = ROOM_API_CLIENT
+        .get(format!(
+            "{base_url}/rooms/{room_id}",
+            base_url = env::var("ROOM_API_URL")?
+        ))
         .send()
-        .await?
-        .error_for_status()?
-        .json::<serde_json::Value>()
         .await?;
 
-    let room = get_room_res.as_object_mut().unwrap(); // TODO: ok_or Error and `?` instead
+    match res.status() {
+        StatusCode::NOT_FOUND => Ok(RoomLookup::NotFound),
+        StatusCode::GONE => Ok(RoomLookup::Deleted),
+        _ => {
+            let room = res.error_for_status()?.json::<Value>().await?;
+            Ok(RoomLookup::Found {
+                hidden: room["hidden"].as_bool().unwrap_or(false),
+            })
+        }
+    }
+}
 
-    _ = room.insert(
-        "ratings".to_owned(),
-        json!({ "availability": availability , "safety": safety, "cleanliness": cleanliness}),
-    );
+/// Checks that the image referenced by a review has been uploaded by `user`, and isn't used by
+/// another review. Images uploaded anonymously can only be used by anonymous reviews.
+pub async fn validate_image_id(
+    db: &Database,
+    user: Option<&User>,
+    image_id: Option<Uuid>,
+) -> Result<(), (StatusCode, String)> {
+    let Some(image_id) = image_id else {
+        return Ok(());
+    };
 
-    _ = client
-        .put(&url)
-        .json(&room)
-        .send()
-        .await?
-        .error_for_status()?;
+    let db_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to get image from db");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured trying to persist review to database".to_owned(),
+        )
+    };
 
-    Ok(())
+    let Some(image) = db
+        .collection::<Image>("images")
+        .find_one(doc! { "id": image_id })
+        .await
+        .map_err(db_error)?
+    else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("No uploaded image found with id {image_id}"),
+        ));
+    };
+
+    if image.uploaded_by.as_deref() != user.map(|u| u.subject.as_str()) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("Image with id {image_id} was uploaded by someone else"),
+        ));
+    }
+
+    let used = db
+        .collection::<Review>("reviews")
+        .find_one(doc! { "imageId": image_id })
+        .await
+        .map_err(db_error)?;
+
+    match used {
+        Some(_) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Image with id {image_id} is already used by another review"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Text and images need to be checked by a moderator before they are published
+pub fn initial_status(review: Option<&str>, image_id: Option<Uuid>) -> ModerationStatus {
+    let has_text = review.is_some_and(|r| !r.trim().is_empty());
+    if has_text || image_id.is_some() {
+        ModerationStatus::Pending
+    } else {
+        ModerationStatus::Approved
+    }
 }
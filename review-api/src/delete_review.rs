@@ -0,0 +1,49 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use mongodb::{bson::doc, Database};
+
+use crate::auth::User;
+use crate::models::Review;
+use crate::outbox::{commit_ratings_changed, start_transaction};
+use crate::reports::resolve_reports;
+use crate::update_review::find_editable_review;
+
+pub async fn delete_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<(), (StatusCode, String)> {
+    let review = find_editable_review(&db, &user, &id).await?;
+
+    tracing::info!(
+        review_id = review.id.to_string(),
+        user = user.subject,
+        "Deleting review"
+    );
+
+    let delete_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Error deleting review");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured trying to delete review from database".to_owned(),
+        )
+    };
+
+    let mut session = start_transaction(&db).await.map_err(delete_error)?;
+
+    db.collection::<Review>("reviews")
+        .delete_one(doc! { "id": review.id })
+        .session(&mut session)
+        .await
+        .map_err(delete_error)?;
+
+    commit_ratings_changed(&db, session, review.room_id)
+        .await
+        .map_err(delete_error)?;
+
+    resolve_reports(&db, review.id, &user).await;
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
@@ -10,7 +10,8 @@ use mongodb::{
 };
 use serde::Deserialize;
 
-use crate::models::Review;
+use crate::auth::User;
+use crate::models::{ModerationStatus, Review};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Params {
@@ -36,9 +37,9 @@ pub async fn get_reviews(
                     ),
                 )
             })?;
-            doc! { "roomId": room_id}
+            doc! { "roomId": room_id, "status": "approved" }
         }
-        None => doc! {},
+        None => doc! { "status": "approved" },
     };
 
     let reviews = collection
@@ -63,3 +64,49 @@ pub async fn get_reviews(
 
     Ok(Json(reviews))
 }
+
+/// Gets a single review.
+///
+/// Reviews which are not approved are only visible to their author and moderators.
+pub async fn get_review_by_id(
+    user: Option<User>,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let not_found = (
+        StatusCode::NOT_FOUND,
+        format!("No review found with id {id}"),
+    );
+
+    let review = db
+        .collection::<Review>("reviews")
+        .find_one(doc! { "id": id })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get review by id from db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting review from database".to_owned(),
+            )
+        })?
+        .ok_or(not_found.clone())?;
+
+    let visible = review.status == ModerationStatus::Approved
+        || user.is_some_and(|user| {
+            user.is_moderator || review.reviewer_id.as_deref() == Some(user.subject.as_str())
+        });
+
+    if visible {
+        Ok(Json(review))
+    } else {
+        Err(not_found)
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    env,
+    io::Cursor,
+    sync::{Arc, LazyLock},
+};
+
+use axum::{
+    extract::{Multipart, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader, ImageResult, Limits,
+};
+use mongodb::{bson::Uuid, Database};
+
+use crate::auth::User;
+use crate::create_review::ALLOW_ANONYMOUS_REVIEWS;
+use crate::models::Image;
+use crate::storage::ImageStore;
+
+/// Sizes images are stored in, as name and max width/height. The first one is the largest
+pub const IMAGE_SIZES: [(&str, u32); 3] = [("full", 2048), ("medium", 800), ("thumbnail", 256)];
+
+const ALLOWED_FORMATS: [(ImageFormat, &str); 3] = [
+    (ImageFormat::Jpeg, "image/jpeg"),
+    (ImageFormat::Png, "image/png"),
+    (ImageFormat::WebP, "image/webp"),
+];
+
+/// Images wider or taller than this are rejected before decoding, to protect against decompression bombs
+const MAX_DIMENSION: u32 = 12_000;
+
+const JPEG_QUALITY: u8 = 85;
+
+pub static MAX_IMAGE_BYTES: LazyLock<usize> = LazyLock::new(|| {
+    env::var("REVIEW_API_MAX_IMAGE_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10 * 1024 * 1024)
+});
+
+struct ProcessedImage {
+    width: u32,
+    height: u32,
+    sizes: Vec<(&'static str, Vec<u8>)>,
+}
+
+/// Accepts an image in the multipart field `image`, to be referenced by id in a review.
+///
+/// Images are re-encoded as JPEG, which removes all metadata including the GPS position.
+pub async fn upload_image(
+    user: Option<User>,
+    State(db): State<Database>,
+    State(store): State<Arc<dyn ImageStore>>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<Image>), (StatusCode, String)> {
+    if user.is_none() && !*ALLOW_ANONYMOUS_REVIEWS {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Anonymous uploads are not allowed. Please sign in".to_owned(),
+        ));
+    }
+
+    let (content_type, data) = read_image_field(&mut multipart).await?;
+
+    if data.len() > *MAX_IMAGE_BYTES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Images can be at most {} bytes", *MAX_IMAGE_BYTES),
+        ));
+    }
+
+    // The content type is given by the client, so it's checked against the actual content as well
+    let format = ALLOWED_FORMATS
+        .iter()
+        .find(|(format, mime)| {
+            content_type.as_deref() == Some(*mime)
+                && image::guess_format(&data).is_ok_and(|f| f == *format)
+        })
+        .map(|(format, _)| *format)
+        .ok_or_else(|| {
+            tracing::warn!(content_type, "Rejected image upload with unsupported type");
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!(
+                    "Images must be one of {:?}",
+                    ALLOWED_FORMATS.map(|(_, mime)| mime)
+                ),
+            )
+        })?;
+
+    let processed = tokio::task::spawn_blocking(move || process_image(&data, format))
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Image processing task failed");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured processing image".to_owned(),
+            )
+        })?
+        .map_err(|e| {
+            tracing::warn!(err = e.to_string(), "Unable to process image");
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Invalid image. {e}"),
+            )
+        })?;
+
+    let image = Image {
+        id: Uuid::new(),
+        width: processed.width,
+        height: processed.height,
+        uploaded_at: Utc::now(),
+        uploaded_by: user.map(|u| u.subject),
+    };
+
+    for (size, data) in processed.sizes {
+        store
+            .put(&image_key(image.id, size), data)
+            .await
+            .map_err(|e| {
+                tracing::error!(err = e.to_string(), "Unable to store image");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "An unexpected error occured storing image".to_owned(),
+                )
+            })?;
+    }
+
+    db.collection::<Image>("images")
+        .insert_one(&image)
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Error persisting image to db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured trying to persist image to database".to_owned(),
+            )
+        })?;
+
+    Ok((StatusCode::CREATED, Json(image)))
+}
+
+pub async fn get_image(
+    Path((id, size)): Path<(String, String)>,
+    State(store): State<Arc<dyn ImageStore>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let Some((size, _)) = IMAGE_SIZES.iter().find(|(name, _)| *name == size) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "Unknown image size {size}. Must be one of {:?}",
+                IMAGE_SIZES.map(|(name, _)| name)
+            ),
+        ));
+    };
+
+    let data = store
+        .get(&image_key(id, size))
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get image from storage");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting image".to_owned(),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("No image found with id {id}"),
+        ))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/jpeg"),
+            // Images are never changed after upload
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        data,
+    ))
+}
+
+async fn read_image_field(
+    multipart: &mut Multipart,
+) -> Result<(Option<String>, Vec<u8>), (StatusCode, String)> {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (e.status(), e.body_text()))?
+    {
+        if field.name() == Some("image") {
+            let content_type = field.content_type().map(String::from);
+            let data = field
+                .bytes()
+                .await
+                .map_err(|e| (e.status(), e.body_text()))?;
+            return Ok((content_type, data.to_vec()));
+        }
+    }
+
+    Err((
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Missing multipart field image".to_owned(),
+    ))
+}
+
+fn process_image(data: &[u8], format: ImageFormat) -> ImageResult<ProcessedImage> {
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    // The orientation is part of the EXIF metadata, which is not kept
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let mut processed = ProcessedImage {
+        width: 0,
+        height: 0,
+        sizes: Vec::new(),
+    };
+
+    for (name, max) in IMAGE_SIZES {
+        let resized = if image.width() > max || image.height() > max {
+            image.resize(max, max, FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+
+        if processed.sizes.is_empty() {
+            processed.width = resized.width();
+            processed.height = resized.height();
+        }
+
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY)
+            .encode_image(&resized.to_rgb8())?;
+        processed.sizes.push((name, encoded));
+    }
+
+    Ok(processed)
+}
+
+fn image_key(id: Uuid, size: &str) -> String {
+    format!("{id}/{size}.jpg")
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageEncoder, RgbImage};
+
+    use super::*;
+
+    /// A JPEG with EXIF metadata holding `orientation` and a GPS position
+    fn jpeg_with_exif(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let mut exif = Vec::new();
+        // Little endian TIFF header, with IFD0 right after it
+        exif.extend_from_slice(b"II\x2a\x00\x08\x00\x00\x00");
+        // IFD0 with Orientation and a pointer to the GPS IFD at offset 38
+        exif.extend_from_slice(&2u16.to_le_bytes());
+        exif.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        exif.extend_from_slice(&orientation.to_le_bytes());
+        exif.extend_from_slice(&[0x00, 0x00]);
+        exif.extend_from_slice(&[0x25, 0x88, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        exif.extend_from_slice(&38u32.to_le_bytes());
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        // GPS IFD with GPSLatitudeRef N
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&[0x01, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00]);
+        exif.extend_from_slice(b"N\x00\x00\x00");
+        exif.extend_from_slice(&0u32.to_le_bytes());
+
+        let image = RgbImage::from_pixel(width, height, image::Rgb([200, 100, 50]));
+        let mut data = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut data);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder
+            .write_image(&image, width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        data
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::new_rgb8(width, height);
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    /// The markers of the segments in a JPEG, up to the start of the image data
+    fn jpeg_markers(data: &[u8]) -> Vec<u8> {
+        let mut markers = Vec::new();
+        let mut pos = 2;
+        while pos + 4 <= data.len() && data[pos] == 0xFF {
+            let marker = data[pos + 1];
+            markers.push(marker);
+            if marker == 0xDA {
+                break;
+            }
+            let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            pos += 2 + len;
+        }
+        markers
+    }
+
+    #[test]
+    fn process_image_removes_exif_metadata() {
+        let data = jpeg_with_exif(64, 32, 1);
+        assert!(jpeg_markers(&data).contains(&0xE1));
+
+        let processed = process_image(&data, ImageFormat::Jpeg).unwrap();
+
+        assert_eq!(processed.sizes.len(), IMAGE_SIZES.len());
+        for (size, encoded) in &processed.sizes {
+            let markers = jpeg_markers(encoded);
+            assert_eq!(markers.last(), Some(&0xDA), "{size} is not a valid JPEG");
+            assert!(!markers.contains(&0xE1), "{size} has an APP1 segment");
+            assert!(!encoded.windows(4).any(|w| w == b"Exif"), "{size} has EXIF");
+        }
+    }
+
+    #[test]
+    fn process_image_keeps_sizes_within_bounds() {
+        let processed = process_image(&png(2100, 1050), ImageFormat::Png).unwrap();
+
+        assert_eq!((processed.width, processed.height), (2048, 1024));
+        for ((size, encoded), (name, max)) in processed.sizes.iter().zip(IMAGE_SIZES) {
+            assert_eq!(*size, name);
+            let image = image::load_from_memory_with_format(encoded, ImageFormat::Jpeg).unwrap();
+            assert_eq!(image.width(), max, "{size} is not scaled to its bound");
+            assert_eq!(image.height(), max / 2, "{size} does not keep aspect ratio");
+        }
+    }
+
+    #[test]
+    fn process_image_does_not_upscale_small_images() {
+        let processed = process_image(&png(100, 50), ImageFormat::Png).unwrap();
+
+        assert_eq!((processed.width, processed.height), (100, 50));
+        for (_, encoded) in &processed.sizes {
+            let image = image::load_from_memory_with_format(encoded, ImageFormat::Jpeg).unwrap();
+            assert_eq!((image.width(), image.height()), (100, 50));
+        }
+    }
+
+    #[test]
+    fn process_image_applies_orientation() {
+        // Orientation 6 means the image is to be rotated 90 degrees clockwise for display
+        let processed = process_image(&jpeg_with_exif(64, 32, 6), ImageFormat::Jpeg).unwrap();
+
+        assert_eq!((processed.width, processed.height), (32, 64));
+        let (_, full) = &processed.sizes[0];
+        let image = image::load_from_memory_with_format(full, ImageFormat::Jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (32, 64));
+    }
+
+    #[test]
+    fn process_image_rejects_non_images() {
+        assert!(process_image(b"not an image", ImageFormat::Jpeg).is_err());
+        assert!(process_image(&png(10, 10), ImageFormat::Jpeg).is_err());
+    }
+
+    #[test]
+    fn process_image_rejects_oversized_images() {
+        assert!(process_image(&png(MAX_DIMENSION + 1, 1), ImageFormat::Png).is_err());
+        assert!(process_image(&png(1, MAX_DIMENSION + 1), ImageFormat::Png).is_err());
+    }
+}
@@ -0,0 +1 @@
+../../room-api/src/jwt.rs
\ No newline at end of file
@@ -1,33 +1,124 @@
 use std::env;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
+use axum::extract::{DefaultBodyLimit, FromRef};
 use axum::http::{self, Method};
 use axum::{routing, Router};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document, Uuid};
 use mongodb::options::ClientOptions;
-use mongodb::{Client, Database};
+use mongodb::{Client, Database, IndexModel};
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 
+use crate::auth::Authenticator;
 use crate::create_review::create_review;
-use crate::get_reviews::get_reviews;
+use crate::delete_review::delete_review;
+use crate::get_reviews::{get_review_by_id, get_reviews};
 use crate::healthcheck::{live, ready};
+use crate::images::{get_image, upload_image, MAX_IMAGE_BYTES};
+use crate::models::Review;
+use crate::moderation::{approve_review, get_moderation_queue, reject_review};
+use crate::patch_review::patch_review;
+use crate::reports::{get_open_reports, report_review};
+use crate::storage::ImageStore;
+use crate::update_review::update_review;
 
+mod auth;
 mod create_review;
+mod delete_review;
 mod get_reviews;
 mod healthcheck;
+mod images;
+mod jwt;
 mod models;
+mod moderation;
+mod outbox;
+mod patch_review;
+mod ratings;
+mod reconcile;
+mod reports;
+mod storage;
+mod update_review;
+
+#[derive(Clone)]
+struct AppState {
+    db: Database,
+    auth: Arc<Authenticator>,
+    images: Arc<dyn ImageStore>,
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Authenticator> {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn ImageStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.images.clone()
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
     let db = get_db_handle().await?;
+    ensure_db_ix(&db).await?;
+    migrate_db(&db).await?;
+
+    // `review-api reconcile [--dry-run]` fixes ratings in room-api instead of serving requests
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("reconcile") => {
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            return reconcile::reconcile(&db, dry_run).await.map_err(|e| e as _);
+        }
+        Some(command) => {
+            return Err(format!(
+                "Unknown command {command}. Usage: review-api [reconcile [--dry-run]]"
+            )
+            .into())
+        }
+        None => {}
+    }
+
+    let auth = Arc::new(Authenticator::from_env().await?);
+    let images = storage::from_env()?;
+
+    tokio::spawn(outbox::run_worker(db.clone()));
 
     let app = Router::new()
         .route("/readyz", routing::get(ready))
         .route("/livez", routing::get(live))
         .route("/reviews", routing::get(get_reviews))
         .route("/reviews", routing::post(create_review))
+        .route(
+            "/reviews/{id}",
+            routing::get(get_review_by_id)
+                .put(update_review)
+                .patch(patch_review)
+                .delete(delete_review),
+        )
+        .route(
+            "/images",
+            // Leave some room for the rest of the multipart body
+            routing::post(upload_image).layer(DefaultBodyLimit::max(*MAX_IMAGE_BYTES + 64 * 1024)),
+        )
+        .route("/images/{id}/{size}", routing::get(get_image))
+        .route("/moderation/queue", routing::get(get_moderation_queue))
+        .route("/reviews/{id}/approve", routing::post(approve_review))
+        .route("/reviews/{id}/reject", routing::post(reject_review))
+        .route("/reviews/{id}/reports", routing::post(report_review))
+        .route("/reports", routing::get(get_open_reports))
         .layer(
             CorsLayer::new()
                 .allow_origin([
@@ -36,10 +127,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "https://dev.stellerom.no".parse()?,
                     "https://www.stellerom.no".parse()?,
                 ])
-                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([http::header::CONTENT_TYPE]),
+                .allow_methods([
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::DELETE,
+                ])
+                .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION]),
         )
-        .with_state(db);
+        .with_state(AppState { db, auth, images });
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
     let listener = TcpListener::bind(addr).await?;
@@ -77,3 +174,62 @@ async fn get_db_handle() -> Result<Database, mongodb::error::Error> {
 
     Ok(mongo_client.database(&db_name))
 }
+
+async fn ensure_db_ix(db: &Database) -> Result<(), mongodb::error::Error> {
+    let collection = db.collection::<Review>("reviews");
+
+    // Ratings are aggregated from the approved reviews of a room after every change to its reviews
+    let ix = IndexModel::builder()
+        .keys(doc! { "roomId": 1, "status": 1 })
+        .build();
+
+    let ix = collection.create_index(ix).await?;
+    tracing::info!("Created index {} (or verified existence)", ix.index_name);
+
+    // Replaced by the index above, which also covers queries on roomId alone
+    if collection
+        .list_index_names()
+        .await?
+        .contains(&"roomId_1".to_owned())
+    {
+        collection.drop_index("roomId_1").await?;
+        tracing::info!("Dropped index roomId_1");
+    }
+    Ok(())
+}
+
+/// Backfills fields added to reviews after they were first created
+async fn migrate_db(db: &Database) -> Result<(), mongodb::error::Error> {
+    let collection = db.collection::<Document>("reviews");
+
+    // Reviews from before moderation were all published
+    let res = collection
+        .update_many(
+            doc! { "status": { "$exists": false } },
+            doc! { "$set": { "status": "approved" } },
+        )
+        .await?;
+    if res.modified_count > 0 {
+        tracing::info!("Set status approved on {} reviews", res.modified_count);
+    }
+
+    // Reviews are addressed by id in the per-review endpoints, so older reviews need one
+    let without_id: Vec<Document> = collection
+        .find(doc! { "id": { "$exists": false } })
+        .await?
+        .try_collect()
+        .await?;
+    for review in &without_id {
+        collection
+            .update_one(
+                doc! { "_id": review.get("_id") },
+                doc! { "$set": { "id": Uuid::new() } },
+            )
+            .await?;
+    }
+    if !without_id.is_empty() {
+        tracing::info!("Set id on {} reviews", without_id.len());
+    }
+
+    Ok(())
+}
@@ -1,12 +1,13 @@
 use bounded_integer::BoundedU8;
 use chrono::prelude::*;
-use mongodb::bson::Uuid;
+use mongodb::bson::{self, Uuid};
 use serde::{Deserialize, Serialize};
 
 pub type StarRating = BoundedU8<1, 5>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Review {
+    pub id: Uuid,
     #[serde(rename = "roomId")]
     pub room_id: Uuid,
     #[serde(rename = "availabilityRating")]
@@ -16,10 +17,106 @@ pub struct Review {
     #[serde(rename = "cleanlinessRating")]
     pub cleanliness_rating: StarRating,
     pub review: Option<String>,
-    #[serde(rename = "imageUrl")]
+    /// Externally hosted image, from before images were uploaded to us
+    #[serde(rename = "imageUrl", default)]
     pub image_url: Option<String>,
+    /// Id of an image uploaded with `POST /images`
+    #[serde(rename = "imageId", default)]
+    pub image_id: Option<Uuid>,
     #[serde(rename = "reviewedAt")]
     pub reviewed_at: DateTime<Utc>,
     #[serde(rename = "reviewedBy")]
     pub reviewed_by: Option<String>,
+    /// Subject id of the signed in user who wrote the review. None for anonymous reviews
+    #[serde(rename = "reviewerId", default)]
+    pub reviewer_id: Option<String>,
+    pub status: ModerationStatus,
+    /// Set when a moderator has approved or rejected the review
+    #[serde(default)]
+    pub moderation: Option<Moderation>,
+}
+
+/// Only approved reviews are shown and count towards room ratings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Moderation {
+    #[serde(rename = "moderatedAt")]
+    pub moderated_at: DateTime<Utc>,
+    #[serde(rename = "moderatedBy")]
+    pub moderated_by: String,
+    pub reason: Option<String>,
+}
+
+/// A user report about an inappropriate review, stored in the `reports` collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewReport {
+    pub id: Uuid,
+    #[serde(rename = "reviewId")]
+    pub review_id: Uuid,
+    pub reason: ReviewReportReason,
+    pub comment: Option<String>,
+    #[serde(rename = "reportedBy")]
+    pub reported_by: String,
+    #[serde(rename = "reportedAt")]
+    pub reported_at: DateTime<Utc>,
+    /// Set when the reported review has been moderated or deleted. None means the report is open
+    #[serde(default)]
+    pub resolved: Option<ReportResolution>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReviewReportReason {
+    Spam,
+    Offensive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportResolution {
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: DateTime<Utc>,
+    #[serde(rename = "resolvedBy")]
+    pub resolved_by: String,
+}
+
+/// The ratings of a room have changed and need to be sent to room-api. Stored in the `outbox` collection
+/// in the same transaction as the change to the reviews, and deleted when delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    #[serde(rename = "roomId")]
+    pub room_id: Uuid,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    /// A bson date, so the worker can query for events which are due
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: bson::DateTime,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    /// Set when delivery has been given up. Failed events are kept for troubleshooting
+    #[serde(rename = "failedAt")]
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+/// An uploaded image, stored in sizes from `IMAGE_SIZES`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Image {
+    pub id: Uuid,
+    /// Width of the largest stored size
+    pub width: u32,
+    /// Height of the largest stored size
+    pub height: u32,
+    #[serde(rename = "uploadedAt")]
+    pub uploaded_at: DateTime<Utc>,
+    /// Subject id of the signed in user who uploaded the image
+    #[serde(rename = "uploadedBy")]
+    pub uploaded_by: Option<String>,
 }
@@ -0,0 +1,170 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Uuid},
+    options::ReturnDocument,
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::User;
+use crate::models::{Moderation, ModerationStatus, Review};
+use crate::outbox::{commit_ratings_changed, start_transaction};
+use crate::reports::resolve_reports;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RejectReview {
+    /// Shown to moderators only
+    pub reason: Option<String>,
+}
+
+/// The fields of a review set when moderating it
+#[derive(Clone, Debug, Serialize)]
+struct ModerationUpdate {
+    status: ModerationStatus,
+    moderation: Option<Moderation>,
+}
+
+/// Reviews awaiting moderation, oldest first
+pub async fn get_moderation_queue(
+    user: User,
+    State(db): State<Database>,
+) -> Result<Json<Vec<Review>>, (StatusCode, String)> {
+    require_moderator(&user)?;
+
+    let reviews = db
+        .collection::<Review>("reviews")
+        .find(doc! { "status": "pending" })
+        .sort(doc! { "reviewedAt": 1 })
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                err = e.to_string(),
+                "Unable to get cursor for pending reviews"
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reviews from database".to_owned(),
+            )
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                err = e.to_string(),
+                "Unable to collect pending reviews into Vec"
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reviews from database".to_owned(),
+            )
+        })?;
+
+    Ok(Json(reviews))
+}
+
+pub async fn approve_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    moderate(&db, &user, &id, ModerationStatus::Approved, None).await
+}
+
+pub async fn reject_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    body: Option<Json<RejectReview>>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    let reason = body.and_then(|Json(body)| body.reason);
+    moderate(&db, &user, &id, ModerationStatus::Rejected, reason).await
+}
+
+async fn moderate(
+    db: &Database,
+    user: &User,
+    id: &str,
+    status: ModerationStatus,
+    reason: Option<String>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    require_moderator(user)?;
+
+    let id = Uuid::parse_str(id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    tracing::info!(
+        review_id = id.to_string(),
+        user = user.subject,
+        "Setting review status to {status:?}"
+    );
+
+    let moderation = Some(Moderation {
+        moderated_at: Utc::now(),
+        moderated_by: user.subject.clone(),
+        reason,
+    });
+
+    let update = bson::to_document(&ModerationUpdate { status, moderation }).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize moderation");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured moderating review".to_owned(),
+        )
+    })?;
+
+    let moderate_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to moderate review");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured moderating review".to_owned(),
+        )
+    };
+
+    let mut session = start_transaction(db).await.map_err(moderate_error)?;
+
+    let review = db
+        .collection::<Review>("reviews")
+        .find_one_and_update(doc! { "id": id }, doc! { "$set": update })
+        .return_document(ReturnDocument::After)
+        .session(&mut session)
+        .await
+        .map_err(moderate_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("No review found with id {id}"),
+        ))?;
+
+    commit_ratings_changed(db, session, review.room_id)
+        .await
+        .map_err(moderate_error)?;
+
+    resolve_reports(db, id, user).await;
+
+    Ok(Json(review))
+}
+
+fn require_moderator(user: &User) -> Result<(), (StatusCode, String)> {
+    if user.is_moderator {
+        Ok(())
+    } else {
+        tracing::warn!(
+            user = user.subject,
+            "Non-moderator attempted to moderate reviews"
+        );
+        Err((
+            StatusCode::FORBIDDEN,
+            "Only moderators are allowed to moderate reviews".to_owned(),
+        ))
+    }
+}
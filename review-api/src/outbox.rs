@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::{
+    bson::{self, doc, Uuid},
+    options::ReturnDocument,
+    ClientSession, Database,
+};
+
+use crate::models::{OutboxEvent, Review};
+use crate::ratings::update_room_ratings;
+
+pub const OUTBOX_COLLECTION: &str = "outbox";
+
+/// How often the worker looks for events when there are none due
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Events are hidden from other workers while being delivered, in case several instances are running
+const DELIVERY_LEASE: Duration = Duration::from_secs(60);
+
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Delivery is given up after this many attempts. About a day with the delays above
+const MAX_ATTEMPTS: u32 = 30;
+
+/// Starts a transaction for changing reviews together with queueing events
+pub async fn start_transaction(db: &Database) -> Result<ClientSession, mongodb::error::Error> {
+    let mut session = db.client().start_session().await?;
+    session.start_transaction().await?;
+    Ok(session)
+}
+
+/// Queues sending the ratings of a room to room-api, as part of the transaction in `session`
+pub async fn enqueue_ratings_changed(
+    db: &Database,
+    session: &mut ClientSession,
+    room_id: Uuid,
+) -> Result<(), mongodb::error::Error> {
+    let event = OutboxEvent {
+        id: Uuid::new(),
+        room_id,
+        created_at: Utc::now(),
+        attempts: 0,
+        next_attempt_at: bson::DateTime::now(),
+        last_error: None,
+        failed_at: None,
+    };
+
+    db.collection::<OutboxEvent>(OUTBOX_COLLECTION)
+        .insert_one(&event)
+        .session(session)
+        .await?;
+
+    Ok(())
+}
+
+/// Queues sending the ratings of a room to room-api, and commits it with the changes to its reviews
+pub async fn commit_ratings_changed(
+    db: &Database,
+    mut session: ClientSession,
+    room_id: Uuid,
+) -> Result<(), mongodb::error::Error> {
+    enqueue_ratings_changed(db, &mut session, room_id).await?;
+    session.commit_transaction().await
+}
+
+/// Delivers queued events to room-api until the process exits
+pub async fn run_worker(db: Database) {
+    tracing::info!("Outbox worker started");
+    loop {
+        match deliver_next(&db).await {
+            // Keep going while there are events due
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => tracing::error!(err = e.to_string(), "Unable to get next outbox event"),
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Delivers the next event which is due, if any. Returns whether there was one
+async fn deliver_next(db: &Database) -> Result<bool, mongodb::error::Error> {
+    let outbox = db.collection::<OutboxEvent>(OUTBOX_COLLECTION);
+    let now = bson::DateTime::now();
+
+    let event = outbox
+        .find_one_and_update(
+            doc! { "failedAt": null, "nextAttemptAt": { "$lte": now } },
+            doc! { "$set": { "nextAttemptAt": after(now, DELIVERY_LEASE) } },
+        )
+        .sort(doc! { "nextAttemptAt": 1 })
+        .return_document(ReturnDocument::After)
+        .await?;
+
+    let Some(event) = event else {
+        return Ok(false);
+    };
+
+    let result = update_room_ratings(&db.collection::<Review>("reviews"), &event.room_id)
+        .await
+        .map_err(|e| e.to_string());
+
+    match result {
+        Ok(()) => {
+            tracing::info!(
+                room_id = event.room_id.to_string(),
+                attempts = event.attempts + 1,
+                "Delivered ratings to room service"
+            );
+            outbox.delete_one(doc! { "id": event.id }).await?;
+        }
+        Err(e) if event.attempts + 1 >= MAX_ATTEMPTS => {
+            tracing::error!(
+                err = e,
+                room_id = event.room_id.to_string(),
+                "Giving up delivering ratings to room service"
+            );
+            outbox
+                .update_one(
+                    doc! { "id": event.id },
+                    doc! {
+                        "$set": { "lastError": e, "failedAt": Utc::now().to_rfc3339() },
+                        "$inc": { "attempts": 1 },
+                    },
+                )
+                .await?;
+        }
+        Err(e) => {
+            let delay = retry_delay(event.attempts);
+            tracing::warn!(
+                err = e,
+                room_id = event.room_id.to_string(),
+                "Unable to deliver ratings to room service. Retrying in {delay:?}"
+            );
+            outbox
+                .update_one(
+                    doc! { "id": event.id },
+                    doc! {
+                        "$set": { "lastError": e, "nextAttemptAt": after(bson::DateTime::now(), delay) },
+                        "$inc": { "attempts": 1 },
+                    },
+                )
+                .await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Exponential backoff, doubling the delay for every failed attempt
+fn retry_delay(previous_attempts: u32) -> Duration {
+    FIRST_RETRY_DELAY
+        .saturating_mul(2_u32.saturating_pow(previous_attempts))
+        .min(MAX_RETRY_DELAY)
+}
+
+fn after(time: bson::DateTime, delay: Duration) -> bson::DateTime {
+    bson::DateTime::from_millis(time.timestamp_millis() + delay.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_from_first_delay() {
+        assert_eq!(retry_delay(0), FIRST_RETRY_DELAY);
+        assert_eq!(retry_delay(1), FIRST_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), FIRST_RETRY_DELAY * 16);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(MAX_ATTEMPTS), MAX_RETRY_DELAY);
+        // Doesn't overflow, even for attempts we would have given up on long ago
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
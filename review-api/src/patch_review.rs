@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::Database;
+use serde_json::Value;
+
+use crate::auth::User;
+use crate::models::Review;
+use crate::update_review::{find_editable_review, save_review_update, UpdateReview};
+
+/// Partially updates a review using JSON Merge Patch (RFC 7386) semantics
+pub async fn patch_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    Json(patch): Json<Value>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    if !patch.is_object() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Patch must be a JSON object".to_owned(),
+        ));
+    }
+
+    let existing = find_editable_review(&db, &user, &id).await?;
+
+    let mut merged = serde_json::to_value(UpdateReview::from(existing.clone())).map_err(|e| {
+        tracing::error!(
+            err = e.to_string(),
+            "Unable to serialize review for patching"
+        );
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured updating review".to_owned(),
+        )
+    })?;
+    json_patch::merge(&mut merged, &patch);
+
+    // Deserializing rejects patches leaving the review invalid, e.g. with ratings out of range
+    let payload = serde_json::from_value::<UpdateReview>(merged).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Invalid review after applying patch");
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid review after applying patch: {e}"),
+        )
+    })?;
+
+    save_review_update(&db, &user, existing, payload).await
+}
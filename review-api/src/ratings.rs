@@ -0,0 +1,443 @@
+use std::{env, sync::LazyLock, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document, Uuid},
+    Collection,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::models::Review;
+
+/// Client for requests to room-api, shared so connections are reused.
+///
+/// The timeout keeps a hanging room-api from holding up reviews being created,
+/// and is well within the delivery lease of outbox events
+pub static ROOM_API_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .connect_timeout(Duration::from_secs(5))
+        .build()
+        .expect("Unable to create room-api client")
+});
+
+/// The rating rooms are assumed to have before they get any reviews, used for ranking
+pub static SCORE_PRIOR_MEAN: LazyLock<f64> = LazyLock::new(|| {
+    env::var("REVIEW_API_SCORE_PRIOR_MEAN")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3.0)
+});
+
+/// How many reviews the prior mean counts as. Higher values need more reviews to move the score
+pub static SCORE_PRIOR_WEIGHT: LazyLock<f64> = LazyLock::new(|| {
+    env::var("REVIEW_API_SCORE_PRIOR_WEIGHT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5.0)
+});
+
+/// Half-life of the weight of reviews in recent averages and the recent score.
+/// When unset every review weighs the same, and recent ratings are left out
+pub static RATING_HALF_LIFE_DAYS: LazyLock<Option<f64>> = LazyLock::new(|| {
+    env::var("REVIEW_API_RATING_HALF_LIFE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|days: &f64| *days > 0.0)
+});
+
+/// Recomputes the ratings of a room from its approved reviews, and updates them in room-api
+pub async fn update_room_ratings(
+    collection: &Collection<Review>,
+    room_id: &Uuid,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Summed up by the database, so popular rooms don't need all their reviews loaded
+    let stats = collection
+        .aggregate(ratings_pipeline(
+            doc! { "roomId": *room_id },
+            *RATING_HALF_LIFE_DAYS,
+        ))
+        .with_type::<RatingStats>()
+        .await?
+        .try_next()
+        .await?;
+
+    let ratings = match stats {
+        Some(stats) => ratings_from_stats(&stats, *RATING_HALF_LIFE_DAYS),
+        // E.g. when the only approved review has been rejected
+        None => Value::Null,
+    };
+
+    put_room_ratings(&ROOM_API_CLIENT, room_id, &ratings).await
+}
+
+/// Sets the ratings of a room in room-api. `Value::Null` removes them
+pub async fn put_room_ratings(
+    client: &Client,
+    room_id: &Uuid,
+    ratings: &Value,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let url = format!(
+        "{base_url}/rooms/{room_id}/ratings",
+        base_url = env::var("ROOM_API_URL")?
+    );
+
+    // The ratings endpoint is only for our own services, authenticated with a shared secret
+    _ = client
+        .put(&url)
+        .bearer_auth(env::var("ROOM_API_SERVICE_SECRET")?)
+        .json(ratings)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Sums over the approved reviews of a room, as computed by `ratings_pipeline`
+#[derive(Debug, Deserialize)]
+pub struct RatingStats {
+    #[serde(rename = "_id")]
+    pub room_id: Uuid,
+    count: u32,
+    totals: CategorySums,
+    /// Sums of the ratings multiplied by the weight of their review
+    weighted: CategorySums,
+    weight: f64,
+    histograms: CategoryHistograms,
+    #[serde(rename = "lastReviewedAt")]
+    last_reviewed_at: Option<bson::DateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategorySums {
+    availability: f64,
+    safety: f64,
+    cleanliness: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryHistograms {
+    availability: [u32; 5],
+    safety: [u32; 5],
+    cleanliness: [u32; 5],
+}
+
+/// Aggregation pipeline computing the `RatingStats` of each room from the approved reviews matching `filter`.
+///
+/// `half_life_days` is normally `RATING_HALF_LIFE_DAYS`.
+pub fn ratings_pipeline(filter: Document, half_life_days: Option<f64>) -> Vec<Document> {
+    // `reviewedAt` is stored as a string, so it is compared as a date rather than character by character.
+    // An unparsable date fails the aggregation, rather than silently counting the review as new
+    let reviewed_at = doc! { "$toDate": "$reviewedAt" };
+
+    // Newer reviews weigh more, so a room which has been renovated isn't stuck with its old ratings.
+    // A review counts half as much for every half-life since it was written, with `$$NOW` as the current time
+    let weight = match half_life_days {
+        Some(half_life) => Bson::Document(doc! {
+            "$pow": [0.5, { "$divide": [
+                { "$max": [0, { "$subtract": ["$$NOW", reviewed_at.clone()] }] },
+                half_life * 86_400_000.0,
+            ] }],
+        }),
+        None => Bson::Double(1.0),
+    };
+
+    let mut group = doc! {
+        "_id": "$roomId",
+        "count": { "$sum": 1 },
+        "weight": { "$sum": weight.clone() },
+        "lastReviewedAt": { "$max": reviewed_at },
+    };
+    let mut totals = Document::new();
+    let mut weighted = Document::new();
+    let mut histograms = Document::new();
+
+    for (category, field) in [
+        ("availability", "$availabilityRating"),
+        ("safety", "$safetyRating"),
+        ("cleanliness", "$cleanlinessRating"),
+    ] {
+        group.insert(format!("{category}Total"), doc! { "$sum": field });
+        group.insert(
+            format!("{category}Weighted"),
+            doc! { "$sum": { "$multiply": [field, weight.clone()] } },
+        );
+        totals.insert(category, format!("${category}Total"));
+        weighted.insert(category, format!("${category}Weighted"));
+
+        let mut histogram = Vec::new();
+        for stars in 1..=5 {
+            group.insert(
+                format!("{category}{stars}"),
+                doc! { "$sum": { "$cond": [{ "$eq": [field, stars] }, 1, 0] } },
+            );
+            histogram.push(Bson::String(format!("${category}{stars}")));
+        }
+        histograms.insert(category, histogram);
+    }
+
+    vec![
+        doc! { "$match": { "$and": [filter, { "status": "approved" }] } },
+        doc! { "$group": group },
+        // Reshaped to match `RatingStats`
+        doc! { "$project": {
+            "count": 1,
+            "weight": 1,
+            "lastReviewedAt": 1,
+            "totals": totals,
+            "weighted": weighted,
+            "histograms": histograms,
+        } },
+    ]
+}
+
+/// The ratings of a room in the format room-api expects.
+///
+/// `half_life_days` is normally `RATING_HALF_LIFE_DAYS`, and must match the one given to `ratings_pipeline`.
+pub fn ratings_from_stats(stats: &RatingStats, half_life_days: Option<f64>) -> Value {
+    let count = f64::from(stats.count);
+    let averages = json!({
+        "availability": stats.totals.availability / count,
+        "safety": stats.totals.safety / count,
+        "cleanliness": stats.totals.cleanliness / count,
+    });
+
+    let recent_averages = (half_life_days.is_some() && stats.weight > 0.0).then(|| {
+        json!({
+            "availability": stats.weighted.availability / stats.weight,
+            "safety": stats.weighted.safety / stats.weight,
+            "cleanliness": stats.weighted.cleanliness / stats.weight,
+        })
+    });
+
+    // Bayesian average of the overall rating of each review, so a single
+    // 5-star review doesn't rank above many good ones
+    let bayesian_average = |sums: &CategorySums, weight: f64| {
+        let overall_total = (sums.availability + sums.safety + sums.cleanliness) / 3.0;
+        (*SCORE_PRIOR_WEIGHT * *SCORE_PRIOR_MEAN + overall_total) / (*SCORE_PRIOR_WEIGHT + weight)
+    };
+    let score = bayesian_average(&stats.totals, count);
+
+    // Decays as reviews age, so it's only current as of the latest change or reconcile
+    let recent_score = half_life_days
+        .is_some()
+        .then(|| bayesian_average(&stats.weighted, stats.weight));
+
+    json!({
+        // Rounded to whole stars for older clients
+        "availability": (stats.totals.availability / count).round() as u8,
+        "safety": (stats.totals.safety / count).round() as u8,
+        "cleanliness": (stats.totals.cleanliness / count).round() as u8,
+        "count": stats.count,
+        "averages": averages,
+        "recentAverages": recent_averages,
+        "histograms": {
+            "availability": stats.histograms.availability,
+            "safety": stats.histograms.safety,
+            "cleanliness": stats.histograms.cleanliness,
+        },
+        "score": score,
+        "recentScore": recent_score,
+        "lastReviewedAt": stats
+            .last_reviewed_at
+            .and_then(|d| DateTime::<Utc>::from_timestamp_millis(d.timestamp_millis())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_stage(pipeline: &[Document]) -> &Document {
+        pipeline[1].get_document("$group").unwrap()
+    }
+
+    /// Stats of `count` reviews summing up to `totals`, weighing `weight` in total
+    fn stats(count: u32, totals: [f64; 3], weighted: [f64; 3], weight: f64) -> RatingStats {
+        let sums = |[availability, safety, cleanliness]: [f64; 3]| CategorySums {
+            availability,
+            safety,
+            cleanliness,
+        };
+        RatingStats {
+            room_id: Uuid::new(),
+            count,
+            totals: sums(totals),
+            weighted: sums(weighted),
+            weight,
+            histograms: CategoryHistograms {
+                availability: [0; 5],
+                safety: [0; 5],
+                cleanliness: [0; 5],
+            },
+            last_reviewed_at: None,
+        }
+    }
+
+    #[test]
+    fn pipeline_matches_approved_reviews() {
+        let room_id = Uuid::new();
+        let pipeline = ratings_pipeline(doc! { "roomId": room_id }, None);
+
+        assert_eq!(pipeline.len(), 3);
+        assert_eq!(
+            pipeline[0],
+            doc! { "$match": { "$and": [{ "roomId": room_id }, { "status": "approved" }] } }
+        );
+    }
+
+    #[test]
+    fn pipeline_weighs_reviews_by_age_of_their_date() {
+        let pipeline = ratings_pipeline(doc! {}, Some(30.0));
+        let group = group_stage(&pipeline);
+
+        let weight = doc! {
+            "$pow": [0.5, { "$divide": [
+                { "$max": [0, { "$subtract": ["$$NOW", { "$toDate": "$reviewedAt" }] }] },
+                30.0 * 86_400_000.0,
+            ] }],
+        };
+        assert_eq!(
+            group.get_document("weight").unwrap(),
+            &doc! { "$sum": &weight }
+        );
+        assert_eq!(
+            group.get_document("safetyWeighted").unwrap(),
+            &doc! { "$sum": { "$multiply": ["$safetyRating", &weight] } }
+        );
+        assert_eq!(
+            group.get_document("lastReviewedAt").unwrap(),
+            &doc! { "$max": { "$toDate": "$reviewedAt" } }
+        );
+    }
+
+    #[test]
+    fn pipeline_weighs_reviews_equally_without_half_life() {
+        let pipeline = ratings_pipeline(doc! {}, None);
+        let group = group_stage(&pipeline);
+
+        assert_eq!(group.get_document("weight").unwrap(), &doc! { "$sum": 1.0 });
+        assert_eq!(
+            group.get_document("cleanlinessWeighted").unwrap(),
+            &doc! { "$sum": { "$multiply": ["$cleanlinessRating", 1.0] } }
+        );
+    }
+
+    #[test]
+    fn pipeline_does_not_hide_unparsable_dates() {
+        for half_life in [None, Some(30.0)] {
+            let pipeline = bson::to_bson(&ratings_pipeline(doc! {}, half_life)).unwrap();
+            assert!(
+                !pipeline.to_string().contains("onError"),
+                "{pipeline} has a fallback for unparsable dates"
+            );
+        }
+    }
+
+    #[test]
+    fn pipeline_output_deserializes_as_stats() {
+        let pipeline = ratings_pipeline(doc! {}, None);
+        let project = pipeline[2].get_document("$project").unwrap();
+        assert_eq!(
+            project.get_document("totals").unwrap(),
+            &doc! {
+                "availability": "$availabilityTotal",
+                "safety": "$safetyTotal",
+                "cleanliness": "$cleanlinessTotal",
+            }
+        );
+        assert_eq!(
+            project
+                .get_document("histograms")
+                .unwrap()
+                .get_array("safety")
+                .unwrap(),
+            &["$safety1", "$safety2", "$safety3", "$safety4", "$safety5"]
+                .map(Bson::from)
+                .to_vec()
+        );
+
+        // Shaped like the output of the pipeline, with `lastReviewedAt` as a date
+        let reviewed_at = bson::DateTime::parse_rfc3339_str("2025-03-01T12:00:00.5Z").unwrap();
+        let stats: RatingStats = bson::from_document(doc! {
+            "_id": Uuid::new(),
+            "count": 2,
+            "weight": 2.0,
+            "lastReviewedAt": reviewed_at,
+            "totals": { "availability": 8.0, "safety": 7.0, "cleanliness": 6.0 },
+            "weighted": { "availability": 8.0, "safety": 7.0, "cleanliness": 6.0 },
+            "histograms": {
+                "availability": [0, 0, 0, 2, 0],
+                "safety": [0, 0, 1, 1, 0],
+                "cleanliness": [0, 0, 2, 0, 0],
+            },
+        })
+        .unwrap();
+
+        assert_eq!(
+            ratings_from_stats(&stats, None)["lastReviewedAt"],
+            json!("2025-03-01T12:00:00.500Z")
+        );
+    }
+
+    #[test]
+    fn ratings_of_a_single_review() {
+        let ratings = ratings_from_stats(&stats(1, [5.0, 4.0, 3.0], [5.0, 4.0, 3.0], 1.0), None);
+
+        assert_eq!(ratings["count"], json!(1));
+        assert_eq!(
+            ratings["averages"],
+            json!({ "availability": 5.0, "safety": 4.0, "cleanliness": 3.0 })
+        );
+        assert_eq!(ratings["availability"], json!(5));
+        assert_eq!(ratings["safety"], json!(4));
+        assert_eq!(ratings["cleanliness"], json!(3));
+        // The prior of 5 reviews of 3 stars, and one review of 4 stars overall
+        assert_eq!(ratings["score"], json!((5.0 * 3.0 + 4.0) / 6.0));
+    }
+
+    #[test]
+    fn many_good_reviews_rank_above_a_single_perfect_one() {
+        let single = ratings_from_stats(&stats(1, [5.0; 3], [5.0; 3], 1.0), None);
+        let many = ratings_from_stats(&stats(20, [85.0; 3], [85.0; 3], 20.0), None);
+
+        assert_eq!(many["averages"]["safety"], json!(4.25));
+        assert_eq!(many["safety"], json!(4));
+        assert!(many["score"].as_f64() > single["score"].as_f64());
+    }
+
+    #[test]
+    fn recent_ratings_are_left_out_without_half_life() {
+        let ratings = ratings_from_stats(&stats(2, [8.0; 3], [3.0; 3], 0.75), None);
+
+        assert_eq!(ratings["recentAverages"], Value::Null);
+        assert_eq!(ratings["recentScore"], Value::Null);
+        assert_eq!(ratings["score"], json!((5.0 * 3.0 + 8.0) / 7.0));
+    }
+
+    #[test]
+    fn recent_ratings_weigh_newer_reviews_more() {
+        // A new 5 star review weighing 1, and an old 1 star review weighing 0.25
+        let ratings = ratings_from_stats(&stats(2, [6.0; 3], [5.25; 3], 1.25), Some(30.0));
+
+        assert_eq!(ratings["averages"]["availability"], json!(3.0));
+        assert_eq!(ratings["recentAverages"]["availability"], json!(4.2));
+        assert_eq!(ratings["recentScore"], json!((5.0 * 3.0 + 5.25) / 6.25));
+        // The score is all-time, regardless of half-life
+        assert_eq!(ratings["score"], json!((5.0 * 3.0 + 6.0) / 7.0));
+    }
+
+    #[test]
+    fn recent_ratings_of_reviews_weighing_nothing() {
+        // Reviews old enough that their weight has rounded down to 0
+        let ratings = ratings_from_stats(&stats(3, [12.0; 3], [0.0; 3], 0.0), Some(30.0));
+
+        assert_eq!(ratings["recentAverages"], Value::Null);
+        assert_eq!(ratings["recentScore"], json!(3.0));
+        assert_eq!(ratings["averages"]["cleanliness"], json!(4.0));
+    }
+}
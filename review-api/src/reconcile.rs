@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::env;
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Uuid},
+    Database,
+};
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use crate::models::Review;
+use crate::ratings::{
+    put_room_ratings, ratings_from_stats, ratings_pipeline, RatingStats, RATING_HALF_LIFE_DAYS,
+};
+
+/// Differences smaller than this are rounding errors rather than mismatches
+const TOLERANCE: f64 = 1e-3;
+
+/// Recomputes the ratings of all rooms from the reviews and fixes the ones room-api has wrong,
+/// e.g. after outbox events have failed. With `dry_run` the differences are only printed.
+///
+/// Recent averages and recent scores drift as reviews age, so this also refreshes them.
+pub async fn reconcile(
+    db: &Database,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = Client::new();
+    let base_url = env::var("ROOM_API_URL")?;
+
+    let mut expected = db
+        .collection::<Review>("reviews")
+        .aggregate(ratings_pipeline(doc! {}, *RATING_HALF_LIFE_DAYS))
+        .with_type::<RatingStats>()
+        .await?
+        .map_ok(|stats| {
+            (
+                stats.room_id,
+                ratings_from_stats(&stats, *RATING_HALF_LIFE_DAYS),
+            )
+        })
+        .try_collect::<HashMap<_, _>>()
+        .await?;
+
+    let mut actual = get_all_room_ratings(&client, &base_url).await?;
+
+    // Rooms with ratings in room-api, but no approved reviews
+    for room_id in actual.keys() {
+        expected.entry(*room_id).or_insert(Value::Null);
+    }
+
+    let mut room_ids = expected.keys().copied().collect::<Vec<_>>();
+    room_ids.sort_by_key(|id| id.to_string());
+
+    let mut mismatches = 0;
+    let mut fixed = 0;
+    for room_id in room_ids {
+        let expected = &expected[&room_id];
+
+        // Hidden rooms aren't listed, so they are looked up one by one
+        let actual = match actual.remove(&room_id) {
+            Some(ratings) => ratings,
+            None => match get_room_ratings(&client, &base_url, &room_id).await? {
+                Some(ratings) => ratings,
+                None => {
+                    println!("{room_id}: has reviews, but the room doesn't exist or is deleted");
+                    continue;
+                }
+            },
+        };
+
+        if !differs(expected, &actual) {
+            continue;
+        }
+
+        mismatches += 1;
+        println!("{room_id}:\n  room-api: {actual}\n  reviews:  {expected}");
+
+        if dry_run {
+            continue;
+        }
+
+        match put_room_ratings(&client, &room_id, expected).await {
+            Ok(()) => fixed += 1,
+            Err(e) => println!("  unable to fix: {e}"),
+        }
+    }
+
+    if dry_run {
+        println!("Dry run: found {mismatches} rooms with wrong ratings");
+    } else {
+        println!("Fixed {fixed} of {mismatches} rooms with wrong ratings");
+    }
+
+    Ok(())
+}
+
+/// Ratings of the listed rooms, which have any
+async fn get_all_room_ratings(
+    client: &Client,
+    base_url: &str,
+) -> Result<HashMap<Uuid, Value>, Box<dyn std::error::Error + Send + Sync>> {
+    let rooms = client
+        .get(format!("{base_url}/rooms"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<Value>>()
+        .await?;
+
+    let mut ratings = HashMap::new();
+    for mut room in rooms {
+        let id = room["id"].as_str().ok_or("Room without id from room-api")?;
+        let id = Uuid::parse_str(id)?;
+        match room["ratings"].take() {
+            Value::Null => {}
+            room_ratings => _ = ratings.insert(id, room_ratings),
+        }
+    }
+
+    Ok(ratings)
+}
+
+/// Ratings of a single room, or None if it doesn't exist or has been deleted
+async fn get_room_ratings(
+    client: &Client,
+    base_url: &str,
+    room_id: &Uuid,
+) -> Result<Option<Value>, Box<dyn std::error::Error + Send + Sync>> {
+    let res = client
+        .get(format!("{base_url}/rooms/{room_id}"))
+        .send()
+        .await?;
+
+    if matches!(res.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Ok(None);
+    }
+
+    let mut room = res.error_for_status()?.json::<Value>().await?;
+    Ok(Some(room["ratings"].take()))
+}
+
+/// Whether ratings differ by more than rounding errors
+fn differs(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Number(expected), Value::Number(actual)) => {
+            match (expected.as_f64(), actual.as_f64()) {
+                (Some(expected), Some(actual)) => (expected - actual).abs() > TOLERANCE,
+                _ => expected != actual,
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() != actual.len()
+                || expected.iter().zip(actual).any(|(e, a)| differs(e, a))
+        }
+        // A missing key is the same as null, e.g. `recentAverages` when there is no half-life
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.keys().chain(actual.keys()).any(|key| {
+                differs(
+                    expected.get(key).unwrap_or(&Value::Null),
+                    actual.get(key).unwrap_or(&Value::Null),
+                )
+            })
+        }
+        (expected, actual) => expected != actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn ratings() -> Value {
+        json!({
+            "availability": 4,
+            "safety": 3,
+            "cleanliness": 5,
+            "count": 3,
+            "averages": { "availability": 4.0, "safety": 3.333333, "cleanliness": 4.666667 },
+            "recentAverages": null,
+            "histograms": {
+                "availability": [0, 0, 1, 1, 1],
+                "safety": [0, 1, 0, 2, 0],
+                "cleanliness": [0, 0, 0, 1, 2],
+            },
+            "score": 3.5,
+            "recentScore": null,
+            "lastReviewedAt": "2025-03-01T12:00:00.500Z",
+        })
+    }
+
+    #[test]
+    fn equal_ratings_do_not_differ() {
+        assert!(!differs(&ratings(), &ratings()));
+        assert!(!differs(&Value::Null, &Value::Null));
+    }
+
+    #[test]
+    fn rounding_errors_do_not_differ() {
+        let mut actual = ratings();
+        actual["averages"]["safety"] = json!(10.0 / 3.0);
+        actual["score"] = json!(3.5000001);
+
+        assert!(!differs(&ratings(), &actual));
+    }
+
+    #[test]
+    fn missing_keys_are_the_same_as_null() {
+        let mut actual = ratings();
+        let ratings_object = actual.as_object_mut().unwrap();
+        ratings_object.remove("recentAverages");
+        ratings_object.remove("recentScore");
+
+        assert!(!differs(&ratings(), &actual));
+        assert!(!differs(&actual, &ratings()));
+    }
+
+    #[test]
+    fn missing_keys_differ_from_values() {
+        let mut actual = ratings();
+        actual.as_object_mut().unwrap().remove("score");
+
+        assert!(differs(&ratings(), &actual));
+        assert!(differs(&actual, &ratings()));
+    }
+
+    #[test]
+    fn nested_averages_differ() {
+        let mut actual = ratings();
+        actual["averages"]["cleanliness"] = json!(4.5);
+
+        assert!(differs(&ratings(), &actual));
+    }
+
+    #[test]
+    fn nested_histograms_differ() {
+        let mut actual = ratings();
+        actual["histograms"]["safety"] = json!([0, 1, 1, 1, 0]);
+        assert!(differs(&ratings(), &actual));
+
+        actual["histograms"]["safety"] = json!([0, 1, 0, 2]);
+        assert!(differs(&ratings(), &actual));
+    }
+
+    #[test]
+    fn missing_ratings_differ_from_ratings() {
+        assert!(differs(&ratings(), &Value::Null));
+        assert!(differs(&Value::Null, &ratings()));
+    }
+}
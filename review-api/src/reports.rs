@@ -0,0 +1,227 @@
+use std::{env, sync::LazyLock};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Uuid},
+    Database,
+};
+use serde::Deserialize;
+
+use crate::auth::User;
+use crate::models::{ReportResolution, Review, ReviewReport, ReviewReportReason};
+use crate::outbox::{enqueue_ratings_changed, start_transaction};
+
+/// Reviews are hidden when this many users have open reports about them
+pub static REPORT_THRESHOLD: LazyLock<u64> = LazyLock::new(|| {
+    env::var("REVIEW_API_REPORT_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3)
+});
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreateReviewReport {
+    pub reason: ReviewReportReason,
+    pub comment: Option<String>,
+}
+
+pub async fn report_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    Json(payload): Json<CreateReviewReport>,
+) -> Result<(StatusCode, Json<ReviewReport>), (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let reviews = db.collection::<Review>("reviews");
+    let reports = db.collection::<ReviewReport>("reports");
+
+    // Only published reviews can be reported
+    let review = reviews
+        .find_one(doc! { "id": id, "status": "approved" })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get review by id from db");
+            report_error()
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("No review found with id {id}"),
+        ))?;
+
+    // Each user counts once towards hiding a review
+    let already_reported = reports
+        .find_one(doc! { "reviewId": id, "reportedBy": &user.subject, "resolved": null })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get reports from db");
+            report_error()
+        })?;
+    if already_reported.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("You have already reported review {id}"),
+        ));
+    }
+
+    let report = ReviewReport {
+        id: Uuid::new(),
+        review_id: id,
+        reason: payload.reason,
+        comment: payload.comment,
+        reported_by: user.subject.clone(),
+        reported_at: Utc::now(),
+        resolved: None,
+    };
+
+    tracing::info!(
+        review_id = id.to_string(),
+        user = user.subject,
+        "Reporting review as {:?}",
+        report.reason
+    );
+
+    reports.insert_one(&report).await.map_err(|e| {
+        tracing::error!(err = e.to_string(), "Error persisting report to db");
+        report_error()
+    })?;
+
+    let open_reports = reports
+        .count_documents(doc! { "reviewId": id, "resolved": null })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to count reports for review");
+            report_error()
+        })?;
+
+    if open_reports < *REPORT_THRESHOLD {
+        return Ok((StatusCode::CREATED, Json(report)));
+    }
+
+    let hide_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to hide reported review");
+        report_error()
+    };
+
+    let mut session = start_transaction(&db).await.map_err(hide_error)?;
+
+    // Sending the review back to moderation hides it until a moderator has looked at it
+    let hidden = reviews
+        .update_one(
+            doc! { "id": id, "status": "approved" },
+            doc! { "$set": { "status": "pending" } },
+        )
+        .session(&mut session)
+        .await
+        .map_err(hide_error)?;
+
+    if hidden.modified_count > 0 {
+        tracing::warn!(
+            review_id = id.to_string(),
+            open_reports,
+            "Hid review until it has been moderated"
+        );
+
+        enqueue_ratings_changed(&db, &mut session, review.room_id)
+            .await
+            .map_err(hide_error)?;
+    }
+
+    session.commit_transaction().await.map_err(hide_error)?;
+
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Reports which haven't been resolved yet, oldest first
+pub async fn get_open_reports(
+    user: User,
+    State(db): State<Database>,
+) -> Result<Json<Vec<ReviewReport>>, (StatusCode, String)> {
+    if !user.is_admin {
+        tracing::warn!(
+            user = user.subject,
+            "Non-admin user attempted to list reports"
+        );
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only admins are allowed to list reports".to_owned(),
+        ));
+    }
+
+    let reports = db
+        .collection::<ReviewReport>("reports")
+        .find(doc! { "resolved": null })
+        .sort(doc! { "reportedAt": 1 })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get cursor for reports");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reports from database".to_owned(),
+            )
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to collect reports into Vec");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reports from database".to_owned(),
+            )
+        })?;
+
+    Ok(Json(reports))
+}
+
+/// Resolves the open reports about a review once it has been moderated or deleted.
+///
+/// The review has already been changed at this point,
+/// so failing to resolve the reports is logged rather than failing the request.
+pub async fn resolve_reports(db: &Database, review_id: Uuid, user: &User) {
+    let resolution = ReportResolution {
+        resolved_at: Utc::now(),
+        resolved_by: user.subject.clone(),
+    };
+
+    let resolution = match bson::to_bson(&resolution) {
+        Ok(resolution) => resolution,
+        Err(e) => {
+            tracing::error!(err = e.to_string(), "Unable to serialize report resolution");
+            return;
+        }
+    };
+
+    if let Err(e) = db
+        .collection::<ReviewReport>("reports")
+        .update_many(
+            doc! { "reviewId": review_id, "resolved": null },
+            doc! { "$set": { "resolved": resolution } },
+        )
+        .await
+    {
+        tracing::error!(
+            err = e.to_string(),
+            review_id = review_id.to_string(),
+            "Unable to resolve reports"
+        );
+    }
+}
+
+fn report_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "An unexpected error occured trying to report review".to_owned(),
+    )
+}
@@ -0,0 +1,108 @@
+use std::{env, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore, PutPayload};
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where uploaded images are stored
+#[async_trait]
+pub trait ImageStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError>;
+
+    /// None if nothing is stored with `key`
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+}
+
+/// Configures image storage from env vars.
+///
+/// `REVIEW_API_IMAGE_STORAGE` selects the backend, either `local` (default) or `s3`.
+pub fn from_env() -> Result<Arc<dyn ImageStore>, Box<dyn std::error::Error>> {
+    let backend = env::var("REVIEW_API_IMAGE_STORAGE").unwrap_or("local".to_owned());
+    match backend.as_str() {
+        "local" => {
+            // Replicas don't share a disk, so images would only be found by the one storing them
+            if let Ok(app) = env::var("CONTAINER_APP_NAME") {
+                return Err(format!(
+                    "Local image storage is not supported in container app {app}. Set REVIEW_API_IMAGE_STORAGE=s3"
+                )
+                .into());
+            }
+            let root = env::var("REVIEW_API_IMAGE_DIR").unwrap_or("./images".to_owned());
+            tracing::info!("Storing images in local directory {root}");
+            Ok(Arc::new(LocalImageStore {
+                root: PathBuf::from(root),
+            }))
+        }
+        "s3" => {
+            let bucket = env::var("REVIEW_API_S3_BUCKET")
+                .map_err(|_| "REVIEW_API_S3_BUCKET must be set when storing images in s3")?;
+
+            // Credentials and region are read from the standard AWS_* env vars
+            let mut builder = AmazonS3Builder::from_env().with_bucket_name(&bucket);
+            if let Ok(endpoint) = env::var("REVIEW_API_S3_ENDPOINT") {
+                // For S3 compatible services such as MinIO
+                builder = builder
+                    .with_allow_http(endpoint.starts_with("http://"))
+                    .with_endpoint(endpoint);
+            }
+
+            tracing::info!("Storing images in s3 bucket {bucket}");
+            Ok(Arc::new(S3ImageStore {
+                store: builder.build()?,
+            }))
+        }
+        other => Err(format!(
+            "Unknown image storage {other}. REVIEW_API_IMAGE_STORAGE must be local or s3"
+        )
+        .into()),
+    }
+}
+
+/// Stores images as files in a local directory
+pub struct LocalImageStore {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl ImageStore for LocalImageStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Stores images in an S3 compatible bucket
+pub struct S3ImageStore {
+    store: object_store::aws::AmazonS3,
+}
+
+#[async_trait]
+impl ImageStore for S3ImageStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        self.store
+            .put(&Path::from(key), PutPayload::from(data))
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.store.get(&Path::from(key)).await {
+            Ok(result) => Ok(Some(result.bytes().await?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
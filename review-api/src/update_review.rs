@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::{
+    bson::{self, doc, Uuid},
+    options::ReturnDocument,
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::User;
+use crate::create_review::{initial_status, validate_image_id};
+use crate::models::{Moderation, ModerationStatus, Review, StarRating};
+use crate::outbox::{commit_ratings_changed, start_transaction};
+
+/// The parts of a review which can be changed after it has been posted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdateReview {
+    #[serde(rename = "availabilityRating")]
+    pub availability_rating: StarRating,
+    #[serde(rename = "safetyRating")]
+    pub safety_rating: StarRating,
+    #[serde(rename = "cleanlinessRating")]
+    pub cleanliness_rating: StarRating,
+    pub review: Option<String>,
+    /// Id of an image uploaded with `POST /images`
+    #[serde(rename = "imageId")]
+    pub image_id: Option<Uuid>,
+}
+
+impl From<Review> for UpdateReview {
+    fn from(review: Review) -> Self {
+        UpdateReview {
+            availability_rating: review.availability_rating,
+            safety_rating: review.safety_rating,
+            cleanliness_rating: review.cleanliness_rating,
+            review: review.review,
+            image_id: review.image_id,
+        }
+    }
+}
+
+/// The fields of a review set when updating it
+#[derive(Clone, Debug, Serialize)]
+struct ReviewUpdate {
+    #[serde(flatten)]
+    review: UpdateReview,
+    status: ModerationStatus,
+    moderation: Option<Moderation>,
+}
+
+pub async fn update_review(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    Json(payload): Json<UpdateReview>,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    let existing = find_editable_review(&db, &user, &id).await?;
+    save_review_update(&db, &user, existing, payload).await
+}
+
+/// Finds the review with `id`, if `user` is allowed to change it.
+///
+/// Only the author of a review and admins can change it.
+/// Anonymous reviews can only be changed by admins.
+pub async fn find_editable_review(
+    db: &Database,
+    user: &User,
+    id: &str,
+) -> Result<Review, (StatusCode, String)> {
+    let id = Uuid::parse_str(id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let review = db
+        .collection::<Review>("reviews")
+        .find_one(doc! { "id": id })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get review by id from db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting review from database".to_owned(),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("No review found with id {id}"),
+        ))?;
+
+    if !user.is_admin && review.reviewer_id.as_deref() != Some(user.subject.as_str()) {
+        tracing::warn!(
+            user = user.subject,
+            review_id = id.to_string(),
+            "User attempted to change another users review"
+        );
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the author of a review or an admin can change it".to_owned(),
+        ));
+    }
+
+    Ok(review)
+}
+
+/// Saves changes to `existing` and queues recomputing the ratings of the room
+pub async fn save_review_update(
+    db: &Database,
+    user: &User,
+    existing: Review,
+    payload: UpdateReview,
+) -> Result<Json<Review>, (StatusCode, String)> {
+    // The image may have been uploaded by the author, when an admin changes someone else's review
+    if payload.image_id != existing.image_id {
+        validate_image_id(db, Some(user), payload.image_id).await?;
+    }
+
+    tracing::info!(
+        review_id = existing.id.to_string(),
+        user = user.subject,
+        "Updating review"
+    );
+
+    // Changes to the text or image by the author need to be moderated again, like new reviews.
+    // Rejected reviews, and reviews pending because of reports, stay that way after other changes
+    let content_changed =
+        payload.review != existing.review || payload.image_id != existing.image_id;
+    let (status, moderation) = if user.is_moderator
+        || !(content_changed || existing.status == ModerationStatus::Approved)
+    {
+        (existing.status, existing.moderation)
+    } else {
+        (
+            initial_status(payload.review.as_deref(), payload.image_id),
+            None,
+        )
+    };
+
+    let update = bson::to_document(&ReviewUpdate {
+        review: payload,
+        status,
+        moderation,
+    })
+    .map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize review update");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured updating review".to_owned(),
+        )
+    })?;
+
+    let update_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to update review");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured updating review".to_owned(),
+        )
+    };
+
+    let mut session = start_transaction(db).await.map_err(update_error)?;
+
+    let review = db
+        .collection::<Review>("reviews")
+        .find_one_and_update(doc! { "id": existing.id }, doc! { "$set": update })
+        .return_document(ReturnDocument::After)
+        .session(&mut session)
+        .await
+        .map_err(update_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("No review found with id {}", existing.id),
+        ))?;
+
+    commit_ratings_changed(db, session, review.room_id)
+        .await
+        .map_err(update_error)?;
+
+    Ok(Json(review))
+}
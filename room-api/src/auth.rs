@@ -0,0 +1,137 @@
+//! Bearer token authentication for users, and a shared secret for our other services.
+//!
+//! Token validation itself lives in jwt.rs, which review-api shares.
+
+use std::{env, sync::Arc};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, header, request::Parts},
+};
+
+use crate::jwt::TokenValidator;
+
+/// Authenticates users by their bearer tokens, and our other services by the shared secret
+#[derive(Debug)]
+pub struct Authenticator {
+    tokens: TokenValidator,
+    admin_role: String,
+    /// Shared secret for requests from our other services
+    service_secret: Option<String>,
+}
+
+/// Another of our services, authenticated with the shared secret in `ROOM_API_SERVICE_SECRET`
+#[derive(Clone, Debug)]
+pub struct Service;
+
+/// A user authenticated with a valid bearer token
+#[derive(Clone, Debug)]
+pub struct User {
+    pub subject: String,
+    pub name: Option<String>,
+    pub is_admin: bool,
+}
+
+impl Authenticator {
+    /// Configures authentication from `ROOM_API_*` env vars. See [`TokenValidator::from_env`].
+    ///
+    /// If `ROOM_API_SERVICE_SECRET` is not set, every request from other services is rejected.
+    pub async fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let service_secret = env::var("ROOM_API_SERVICE_SECRET")
+            .ok()
+            .filter(|s| !s.is_empty());
+        if service_secret.is_none() {
+            tracing::warn!(
+                "ROOM_API_SERVICE_SECRET not set. Requests from other services will be rejected"
+            );
+        }
+
+        Ok(Authenticator {
+            tokens: TokenValidator::from_env("ROOM_API").await?,
+            admin_role: env::var("ROOM_API_ADMIN_ROLE").unwrap_or("admin".to_owned()),
+            service_secret,
+        })
+    }
+
+    pub async fn authenticate(&self, token: &str) -> Result<User, String> {
+        let claims = self.tokens.validate(token).await?;
+
+        Ok(User {
+            is_admin: claims.has_role(&self.admin_role),
+            subject: claims.subject,
+            name: claims.name,
+        })
+    }
+
+    /// Whether `token` is the shared secret for our other services
+    pub fn is_service_secret(&self, token: &str) -> bool {
+        self.service_secret
+            .as_deref()
+            .is_some_and(|secret| constant_time_eq(secret.as_bytes(), token.as_bytes()))
+    }
+}
+
+impl<S> FromRequestParts<S> for User
+where
+    Arc<Authenticator>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                "Missing bearer token in Authorization header".to_owned(),
+            ))?;
+
+        let authenticator = Arc::<Authenticator>::from_ref(state);
+        authenticator.authenticate(token).await.map_err(|e| {
+            tracing::warn!(err = e, "Rejected bearer token");
+            (
+                StatusCode::UNAUTHORIZED,
+                format!("Invalid bearer token. {e}"),
+            )
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for Service
+where
+    Arc<Authenticator>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                "Missing bearer token in Authorization header".to_owned(),
+            ))?;
+
+        let authenticator = Arc::<Authenticator>::from_ref(state);
+        if authenticator.is_service_secret(token) {
+            Ok(Service)
+        } else {
+            tracing::warn!("Rejected service credentials");
+            Err((
+                StatusCode::UNAUTHORIZED,
+                "Invalid service credentials".to_owned(),
+            ))
+        }
+    }
+}
+
+/// Compares secrets in time independent of where they differ, so they can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
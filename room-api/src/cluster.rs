@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use crate::geo::to_web_mercator;
+use crate::models::{ChangingRoom, Location, Ratings};
+
+/// Rooms are no longer clustered from this zoom level and up
+pub const MAX_CLUSTER_ZOOM: u8 = 15;
+
+/// Size of the grid cells rooms are grouped into, in screen pixels (assuming 256px tiles)
+const CLUSTER_CELL_SIZE_PX: f64 = 60.0;
+
+#[derive(Clone, Debug)]
+pub enum Clustered {
+    Room(Box<ChangingRoom>),
+    Cluster {
+        id: String,
+        location: Location,
+        point_count: usize,
+        average_rating: Option<f64>,
+    },
+}
+
+/// Groups rooms which would be drawn close to each other on a map at the given zoom level.
+///
+/// Uses a simple grid based approach in web mercator pixel space.
+/// Rooms which end up alone in their grid cell are returned as is.
+pub fn cluster_rooms(rooms: Vec<ChangingRoom>, zoom: u8) -> Vec<Clustered> {
+    if zoom >= MAX_CLUSTER_ZOOM {
+        return rooms
+            .into_iter()
+            .map(|r| Clustered::Room(Box::new(r)))
+            .collect();
+    }
+
+    let world_size_px = 256.0 * 2_f64.powi(i32::from(zoom));
+
+    let mut cells: BTreeMap<(i64, i64), Vec<ChangingRoom>> = BTreeMap::new();
+    for room in rooms {
+        let (x, y) = to_web_mercator(room.location);
+        let (x, y) = (x * world_size_px, y * world_size_px);
+        let cell = (
+            (x / CLUSTER_CELL_SIZE_PX).floor() as i64,
+            (y / CLUSTER_CELL_SIZE_PX).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(room);
+    }
+
+    cells
+        .into_iter()
+        .map(|((cell_x, cell_y), mut rooms)| {
+            if rooms.len() == 1 {
+                return Clustered::Room(Box::new(rooms.remove(0)));
+            }
+
+            let point_count = rooms.len();
+            let location = Location {
+                lat: rooms.iter().map(|r| r.location.lat).sum::<f64>() / point_count as f64,
+                lng: rooms.iter().map(|r| r.location.lng).sum::<f64>() / point_count as f64,
+            };
+
+            let rated = rooms
+                .iter()
+                .filter_map(|r| r.ratings.as_ref().map(average_rating))
+                .collect::<Vec<_>>();
+            let average_rating =
+                (!rated.is_empty()).then(|| rated.iter().sum::<f64>() / rated.len() as f64);
+
+            Clustered::Cluster {
+                id: format!("cluster-{zoom}-{cell_x}-{cell_y}"),
+                location,
+                point_count,
+                average_rating,
+            }
+        })
+        .collect()
+}
+
+fn average_rating(ratings: &Ratings) -> f64 {
+    match &ratings.averages {
+        Some(averages) => (averages.availability + averages.safety + averages.cleanliness) / 3.0,
+        None => {
+            (f64::from(ratings.availability.get())
+                + f64::from(ratings.safety.get())
+                + f64::from(ratings.cleanliness.get()))
+                / 3.0
+        }
+    }
+}
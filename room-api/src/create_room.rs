@@ -1,24 +1,36 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
 use geojson::{Geometry, Value};
 use mongodb::{bson::Uuid, Database};
 use serde::Deserialize;
 
-use crate::models::{ChangingRoom, Location};
+use crate::auth::User;
+use crate::history::{record_change, start_transaction};
+use crate::models::{Attributes, ChangingRoom, Location, RoomChange};
+use crate::revision::etag;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct CreateChangingRoom {
     pub name: String,
     pub location: Location,
+    #[serde(default)]
+    pub attributes: Attributes,
 }
 
 pub async fn create_room(
+    user: User,
     State(db): State<Database>,
     Json(payload): Json<CreateChangingRoom>,
-) -> Result<(StatusCode, Json<ChangingRoom>), (StatusCode, String)> {
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     let collection = db.collection::<ChangingRoom>("rooms");
 
     let created = ChangingRoom {
         id: Uuid::new(),
+        revision: 1,
         external_id: None,
         name: payload.name,
         location: payload.location,
@@ -27,15 +39,47 @@ pub async fn create_room(
             payload.location.lat,
         ])),
         ratings: None,
+        attributes: payload.attributes,
+        osm_tags: None,
+        deleted: None,
+        hidden: false,
     };
 
-    collection.insert_one(&created).await.map_err(|e| {
+    tracing::info!(
+        room_id = created.id.to_string(),
+        user = user.subject,
+        "Creating room"
+    );
+
+    let persist_error = |e: mongodb::error::Error| {
         tracing::error!(err = e.to_string(), "Error persisting room to db");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "An unexpected error occured trying to persist room to database".to_owned(),
         )
-    })?;
+    };
+
+    let mut session = start_transaction(&db).await.map_err(persist_error)?;
+    collection
+        .insert_one(&created)
+        .session(&mut session)
+        .await
+        .map_err(persist_error)?;
+    record_change(
+        &db,
+        &mut session,
+        RoomChange::Create,
+        &user,
+        None,
+        created.clone(),
+    )
+    .await
+    .map_err(persist_error)?;
+    session.commit_transaction().await.map_err(persist_error)?;
 
-    Ok((StatusCode::CREATED, Json(created)))
+    Ok((
+        StatusCode::CREATED,
+        [(header::ETAG, etag(created.revision))],
+        Json(created),
+    ))
 }
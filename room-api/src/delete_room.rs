@@ -1,18 +1,41 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
 };
+use chrono::Utc;
 use mongodb::{
-    bson::{doc, Uuid},
+    bson::{self, doc, Uuid},
     Database,
 };
+use serde::Deserialize;
 
-use crate::models::ChangingRoom;
+use crate::auth::User;
+use crate::models::{RoomChange, Tombstone};
+use crate::revision::write_room;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Params {
+    reason: Option<String>,
+}
 
 pub async fn delete_room(
+    user: User,
     Path(id): Path<String>,
+    Query(params): Query<Params>,
+    headers: HeaderMap,
     State(db): State<Database>,
 ) -> Result<(), (StatusCode, String)> {
+    if !user.is_admin {
+        tracing::warn!(
+            user = user.subject,
+            "Non-admin user attempted to delete room"
+        );
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only admins are allowed to delete rooms".to_owned(),
+        ));
+    }
+
     let id = Uuid::parse_str(&id).map_err(|e| {
         tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
         (
@@ -21,18 +44,30 @@ pub async fn delete_room(
         )
     })?;
 
-    let collection = db.collection::<ChangingRoom>("rooms");
-
-    collection
-        .delete_one(doc! { "id": id })
-        .await
-        .map_err(|e| {
-            tracing::error!(err = e.to_string(), "Error deleting changing room");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "An unexpected error occured trying to delete room from database".to_owned(),
-            )
-        })?;
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Deleting room"
+    );
+
+    let tombstone = Tombstone {
+        deleted_at: Utc::now(),
+        deleted_by: user.subject.clone(),
+        reason: params.reason,
+    };
+
+    let tombstone = bson::to_bson(&tombstone).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize tombstone");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured trying to delete room".to_owned(),
+        )
+    })?;
+
+    write_room(&db, id, &headers, &user, RoomChange::Delete, |_| {
+        Ok(Some(doc! { "deleted": tombstone.clone() }))
+    })
+    .await?;
 
     Ok(())
 }
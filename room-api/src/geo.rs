@@ -0,0 +1,199 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use mongodb::bson::{Document, doc};
+
+use crate::models::Location;
+
+/// Max zoom level accepted for map views and tiles
+pub const MAX_ZOOM: u8 = 22;
+
+/// Latitudes further north or south than this are outside the web mercator projection
+const MAX_MERCATOR_LAT: f64 = 85.051_128_78;
+
+/// A WGS84 bounding box in GeoJSON order: min lng, min lat, max lng, max lat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min_lng: f64,
+    pub min_lat: f64,
+    pub max_lng: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    /// Smallest bounding box containing every location, or None if there are no locations
+    pub fn around(locations: impl IntoIterator<Item = Location>) -> Option<Self> {
+        locations.into_iter().fold(None, |bbox, loc| {
+            Some(match bbox {
+                None => BoundingBox {
+                    min_lng: loc.lng,
+                    min_lat: loc.lat,
+                    max_lng: loc.lng,
+                    max_lat: loc.lat,
+                },
+                Some(b) => BoundingBox {
+                    min_lng: b.min_lng.min(loc.lng),
+                    min_lat: b.min_lat.min(loc.lat),
+                    max_lng: b.max_lng.max(loc.lng),
+                    max_lat: b.max_lat.max(loc.lat),
+                },
+            })
+        })
+    }
+
+    /// Mongo filter matching rooms whose `location` is inside the bounding box.
+    ///
+    /// Compared as plain latitude and longitude ranges, like the box drawn on a map,
+    /// since the edges of a `$geometry` polygon follow great circles rather than lines of latitude.
+    /// A bounding box with `min_lng > max_lng` crosses the antimeridian.
+    pub fn to_filter(self) -> Document {
+        let mut filter = doc! {
+            "location.lat": { "$gte": self.min_lat, "$lte": self.max_lat },
+        };
+
+        if self.min_lng <= self.max_lng {
+            filter.insert(
+                "location.lng",
+                doc! { "$gte": self.min_lng, "$lte": self.max_lng },
+            );
+        } else {
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "location.lng": { "$gte": self.min_lng } },
+                    doc! { "location.lng": { "$lte": self.max_lng } },
+                ],
+            );
+        }
+
+        filter
+    }
+
+    /// Bounding box of the web mercator tile `z/x/y`, grown by `buffer` (a fraction of the tile size) in every direction
+    ///
+    /// The buffer is clamped at the antimeridian, so tiles never wrap around, and the zoom 0 tile covers every longitude.
+    pub fn of_tile(z: u8, x: u32, y: u32, buffer: f64) -> Self {
+        let n = 2_f64.powi(i32::from(z));
+        let lng = |x: f64| (x / n * 360.0 - 180.0).clamp(-180.0, 180.0);
+        let lat = |y: f64| {
+            (PI * (1.0 - 2.0 * y / n))
+                .sinh()
+                .atan()
+                .to_degrees()
+                .clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT)
+        };
+
+        BoundingBox {
+            min_lng: lng(f64::from(x) - buffer),
+            min_lat: lat(f64::from(y) + 1.0 + buffer),
+            max_lng: lng(f64::from(x) + 1.0 + buffer),
+            max_lat: lat(f64::from(y) - buffer),
+        }
+    }
+
+    pub fn to_vec(self) -> Vec<f64> {
+        vec![self.min_lng, self.min_lat, self.max_lng, self.max_lat]
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("bbox must be four comma separated numbers: {e}"))?;
+
+        let [min_lng, min_lat, max_lng, max_lat] = parts[..] else {
+            return Err(format!(
+                "bbox must be minLng,minLat,maxLng,maxLat but got {} values",
+                parts.len()
+            ));
+        };
+
+        if !(-180.0..=180.0).contains(&min_lng)
+            || !(-180.0..=180.0).contains(&max_lng)
+            || !(-90.0..=90.0).contains(&min_lat)
+            || !(-90.0..=90.0).contains(&max_lat)
+        {
+            return Err(
+                "bbox longitudes must be within [-180, 180] and latitudes within [-90, 90]"
+                    .to_owned(),
+            );
+        }
+
+        // A min longitude east of the max longitude means the bbox crosses the antimeridian, as in GeoJSON
+        if min_lng == max_lng || min_lat >= max_lat {
+            return Err(
+                "bbox min latitude must be smaller than max latitude, and longitudes must differ"
+                    .to_owned(),
+            );
+        }
+
+        Ok(BoundingBox {
+            min_lng,
+            min_lat,
+            max_lng,
+            max_lat,
+        })
+    }
+}
+
+/// Projects a location to web mercator coordinates, normalized to [0, 1] with y pointing south
+pub fn to_web_mercator(location: Location) -> (f64, f64) {
+    let x = (location.lng + 180.0) / 360.0;
+    let lat = location
+        .lat
+        .clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT)
+        .to_radians();
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0;
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_tile_covers_every_longitude() {
+        for buffer in [0.0, 0.1] {
+            let bbox = BoundingBox::of_tile(0, 0, 0, buffer);
+            assert_eq!((bbox.min_lng, bbox.max_lng), (-180.0, 180.0));
+            assert!(bbox.min_lat < -85.0 && bbox.max_lat > 85.0);
+            assert_eq!(
+                bbox.to_filter().get_document("location.lng").unwrap(),
+                &doc! { "$gte": -180.0, "$lte": 180.0 }
+            );
+        }
+    }
+
+    #[test]
+    fn low_zoom_tiles_split_the_world() {
+        let cases = [
+            ((1, 0, 0), (-180.0, 0.0, 0.0, MAX_MERCATOR_LAT)),
+            ((1, 1, 1), (0.0, -MAX_MERCATOR_LAT, 180.0, 0.0)),
+            ((2, 3, 0), (90.0, 66.513_260_443, 180.0, MAX_MERCATOR_LAT)),
+        ];
+
+        for ((z, x, y), (min_lng, min_lat, max_lng, max_lat)) in cases {
+            let bbox = BoundingBox::of_tile(z, x, y, 0.0);
+            let expected = [min_lng, min_lat, max_lng, max_lat];
+            for (actual, expected) in bbox.to_vec().into_iter().zip(expected) {
+                assert!(
+                    (actual - expected).abs() < 1e-6,
+                    "tile {z}/{x}/{y}: got {bbox:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tile_buffer_is_clamped_at_the_antimeridian() {
+        let bbox = BoundingBox::of_tile(3, 7, 4, 0.25);
+        assert_eq!(bbox.max_lng, 180.0);
+        assert!(bbox.min_lng < bbox.max_lng);
+        assert!(!bbox.to_filter().contains_key("$or"));
+    }
+}
@@ -2,19 +2,31 @@ use std::sync::LazyLock;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{StatusCode, header},
     response::IntoResponse,
 };
+use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
-use geojson::{Feature, FeatureCollection, JsonObject, feature::Id};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, feature::Id};
 use mongodb::{
     Database,
-    bson::{Uuid, doc},
+    bson::{self, Bson, Document, Uuid, doc},
+    options::FindOptions,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::models::ChangingRoom;
+use crate::cluster::{Clustered, cluster_rooms};
+use crate::geo::{BoundingBox, MAX_ZOOM};
+use crate::models::{ChangingRoom, Placement};
+use crate::opening_hours::OpeningHours;
+use crate::revision::etag;
+
+const DEFAULT_NEARBY_RADIUS_METRES: f64 = 5_000.0;
+const MAX_NEARBY_RADIUS_METRES: f64 = 100_000.0;
+const DEFAULT_NEARBY_LIMIT: u32 = 50;
+const MAX_NEARBY_LIMIT: u32 = 500;
 
 static GENERIC_DB_ERROR: LazyLock<(StatusCode, String)> = LazyLock::new(|| {
     (
@@ -23,13 +35,149 @@ static GENERIC_DB_ERROR: LazyLock<(StatusCode, String)> = LazyLock::new(|| {
     )
 });
 
+/// Query parameters for only including rooms with certain attributes
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AttributeFilter {
+    wheelchair: Option<bool>,
+    placement: Option<Placement>,
+    fee: Option<bool>,
+    indoor: Option<bool>,
+    level: Option<String>,
+    sink: Option<bool>,
+    #[serde(rename = "nursingArea")]
+    nursing_area: Option<bool>,
+    #[serde(rename = "diapersForSale")]
+    diapers_for_sale: Option<bool>,
+}
+
+impl AttributeFilter {
+    /// Adds conditions for the requested attributes to `filter`
+    pub fn apply(&self, mut filter: Document) -> Document {
+        let flags = [
+            ("attributes.wheelchair", self.wheelchair),
+            ("attributes.fee", self.fee),
+            ("attributes.indoor", self.indoor),
+            ("attributes.sink", self.sink),
+            ("attributes.nursingArea", self.nursing_area),
+            ("attributes.diapersForSale", self.diapers_for_sale),
+        ];
+        for (field, value) in flags {
+            if let Some(value) = value {
+                filter.insert(field, value);
+            }
+        }
+
+        if let Some(placement) = self.placement {
+            // Matches rooms where placement contains the requested value
+            filter.insert(
+                "attributes.placement",
+                bson::to_bson(&placement).unwrap_or_default(),
+            );
+        }
+        if let Some(level) = &self.level {
+            filter.insert("attributes.level", level);
+        }
+        filter
+    }
+}
+
+/// Query parameters for only including rooms which are open at a given time
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct OpenFilter {
+    /// RFC 3339 timestamp
+    #[serde(rename = "openAt")]
+    open_at: Option<String>,
+    #[serde(rename = "openNow")]
+    open_now: Option<bool>,
+}
+
+impl OpenFilter {
+    /// The time rooms must be open at, if any
+    fn time(&self) -> Result<Option<DateTime<Utc>>, (StatusCode, String)> {
+        match (&self.open_at, self.open_now) {
+            (Some(_), Some(true)) => Err((
+                StatusCode::BAD_REQUEST,
+                "openAt and openNow can not be combined".to_owned(),
+            )),
+            (Some(open_at), _) => DateTime::parse_from_rfc3339(open_at)
+                .map(|t| Some(t.to_utc()))
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("openAt must be an RFC 3339 timestamp. {e}"),
+                    )
+                }),
+            (None, Some(true)) => Ok(Some(Utc::now())),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// Removes rooms which aren't open at the requested time.
+    /// Rooms with unknown opening hours are removed as well
+    fn retain_open(&self, rooms: &mut Vec<ChangingRoom>) -> Result<(), (StatusCode, String)> {
+        if let Some(at) = self.time()? {
+            rooms.retain(|room| opening_hours(room).is_some_and(|oh| oh.is_open(at)));
+        }
+        Ok(())
+    }
+}
+
+/// Query parameter for the order of rooms in listings
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SortParams {
+    sort: Option<RoomSort>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomSort {
+    /// Best ranked first, by the score computed from the reviews. Rooms without reviews come last
+    Score,
+    /// Closest first
+    Distance,
+    /// Most recently reviewed first. Rooms without reviews come last
+    Recent,
+}
+
+impl SortParams {
+    /// Sort document for listings without a point to measure distance from
+    fn listing_sort(&self) -> Result<Option<Document>, (StatusCode, String)> {
+        match self.sort {
+            Some(RoomSort::Score) => Ok(Some(doc! { "ratings.score": -1 })),
+            Some(RoomSort::Recent) => Ok(Some(doc! { "ratings.lastReviewedAt": -1 })),
+            Some(RoomSort::Distance) => Err((
+                StatusCode::BAD_REQUEST,
+                "sort=distance is only supported when finding nearby rooms".to_owned(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Sort document for nearby rooms, which are already sorted by distance
+    fn nearby_sort(&self) -> Option<Document> {
+        match self.sort {
+            Some(RoomSort::Score) => Some(doc! { "ratings.score": -1, "distance": 1 }),
+            Some(RoomSort::Recent) => Some(doc! { "ratings.lastReviewedAt": -1, "distance": 1 }),
+            Some(RoomSort::Distance) | None => None,
+        }
+    }
+}
+
 pub async fn get_all_rooms(
+    Query(attributes): Query<AttributeFilter>,
+    Query(open): Query<OpenFilter>,
+    Query(sort): Query<SortParams>,
     State(db): State<Database>,
 ) -> Result<Json<Vec<ChangingRoom>>, (StatusCode, String)> {
+    // Validate before querying the db
+    open.time()?;
+    let sort = sort.listing_sort()?;
+
     let collection = db.collection::<ChangingRoom>("rooms");
 
-    let rooms = collection
-        .find(doc! {})
+    let mut rooms = collection
+        .find(only_visible(attributes.apply(doc! {})))
+        .with_options(FindOptions::builder().sort(sort).build())
         .await
         .map_err(|e| {
             tracing::error!(err = e.to_string(), "Unable to get cursor for all rooms");
@@ -42,16 +190,52 @@ pub async fn get_all_rooms(
             GENERIC_DB_ERROR.clone()
         })?;
 
+    open.retain_open(&mut rooms)?;
+
     Ok(Json(rooms))
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct ParamsV2 {
+    /// Only include rooms within `minLng,minLat,maxLng,maxLat`
+    bbox: Option<String>,
+    /// Map zoom level. When set, rooms close to each other are grouped into cluster features
+    zoom: Option<u8>,
+}
+
 pub async fn get_all_rooms_v2(
+    Query(params): Query<ParamsV2>,
+    Query(attributes): Query<AttributeFilter>,
+    Query(open): Query<OpenFilter>,
+    Query(sort): Query<SortParams>,
     State(db): State<Database>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    open.time()?;
+    let sort = sort.listing_sort()?;
+
+    if params.zoom.is_some_and(|zoom| zoom > MAX_ZOOM) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("zoom must be between 0 and {MAX_ZOOM}"),
+        ));
+    }
+
+    let filter = match params.bbox {
+        Some(bbox) => bbox
+            .parse::<BoundingBox>()
+            .map_err(|e| {
+                tracing::error!(err = e, "Unable to parse bbox");
+                (StatusCode::BAD_REQUEST, format!("Invalid bbox. {e}"))
+            })?
+            .to_filter(),
+        None => doc! {},
+    };
+
     let collection = db.collection::<ChangingRoom>("rooms");
 
-    let rooms: Vec<ChangingRoom> = collection
-        .find(doc! {})
+    let mut rooms: Vec<ChangingRoom> = collection
+        .find(only_visible(attributes.apply(filter)))
+        .with_options(FindOptions::builder().sort(sort).build())
         .await
         .map_err(|e| {
             tracing::error!(err = e.to_string(), "Unable to get cursor for all rooms");
@@ -64,38 +248,80 @@ pub async fn get_all_rooms_v2(
             GENERIC_DB_ERROR.clone()
         })?;
 
-    let rooms_geo = rooms
+    open.retain_open(&mut rooms)?;
+
+    let now = Utc::now();
+    let bbox = BoundingBox::around(rooms.iter().map(|r| r.location)).map(BoundingBox::to_vec);
+    let rooms_geo = match params.zoom {
+        Some(zoom) => cluster_rooms(rooms, zoom)
+            .into_iter()
+            .map(|clustered| clustered_to_feature(clustered, now))
+            .collect::<Vec<_>>(),
+        None => rooms
+            .into_iter()
+            .map(|room| room_to_feature(room, now))
+            .collect::<Vec<_>>(),
+    };
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(FeatureCollection {
+            bbox,
+            features: rooms_geo,
+            foreign_members: None,
+        }),
+    ))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NearbyParams {
+    lat: f64,
+    lng: f64,
+    /// Max distance from `lat`/`lng` in metres
+    radius: Option<f64>,
+    limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NearbyChangingRoom {
+    #[serde(flatten)]
+    pub room: ChangingRoom,
+    /// Distance from the requested point in metres
+    pub distance: f64,
+}
+
+pub async fn get_nearby_rooms(
+    Query(params): Query<NearbyParams>,
+    Query(attributes): Query<AttributeFilter>,
+    Query(sort): Query<SortParams>,
+    State(db): State<Database>,
+) -> Result<Json<Vec<NearbyChangingRoom>>, (StatusCode, String)> {
+    let rooms = find_nearby_rooms(&db, &params, &attributes, &sort)
+        .await?
         .into_iter()
-        .map(|r| {
-            // Properties
-            let mut props = JsonObject::new();
-            props.insert(String::from("name"), Value::String(r.name));
-            props.insert(
-                String::from("ratings"),
-                serde_json::to_value(r.ratings)
-                    .inspect_err(|e| {
-                        tracing::error!(
-                            err = e.to_string(),
-                            room_id = r.id.to_string(),
-                            "Unable to serialize rating"
-                        );
-                    })
-                    .unwrap(),
-            );
-            if let Some(ext_id) = r.external_id {
-                props.insert(String::from("externalId"), Value::String(ext_id));
-            } else {
-                props.insert(String::from("externalId"), Value::Null);
-            }
+        .map(|(room, distance)| NearbyChangingRoom { room, distance })
+        .collect();
 
-            // Geo Feature
-            Feature {
-                bbox: None,
-                geometry: Some(r.location_geo),
-                id: Some(Id::String(r.id.to_string())),
-                properties: Some(props),
-                foreign_members: None,
-            }
+    Ok(Json(rooms))
+}
+
+pub async fn get_nearby_rooms_v2(
+    Query(params): Query<NearbyParams>,
+    Query(attributes): Query<AttributeFilter>,
+    Query(sort): Query<SortParams>,
+    State(db): State<Database>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let rooms = find_nearby_rooms(&db, &params, &attributes, &sort).await?;
+
+    let now = Utc::now();
+    let bbox = BoundingBox::around(rooms.iter().map(|(r, _)| r.location)).map(BoundingBox::to_vec);
+    let rooms_geo = rooms
+        .into_iter()
+        .map(|(room, distance)| {
+            let mut feature = room_to_feature(room, now);
+            feature.set_property("distance", distance);
+            feature
         })
         .collect::<Vec<_>>();
 
@@ -103,17 +329,101 @@ pub async fn get_all_rooms_v2(
         StatusCode::OK,
         [(header::CONTENT_TYPE, "application/geo+json")],
         Json(FeatureCollection {
-            bbox: None,
+            bbox,
             features: rooms_geo,
             foreign_members: None,
         }),
     ))
 }
 
+/// Finds rooms within `params.radius` of the given point,
+/// sorted by distance (closest first) unless another order is requested
+async fn find_nearby_rooms(
+    db: &Database,
+    params: &NearbyParams,
+    attributes: &AttributeFilter,
+    sort: &SortParams,
+) -> Result<Vec<(ChangingRoom, f64)>, (StatusCode, String)> {
+    if !(-90.0..=90.0).contains(&params.lat) || !(-180.0..=180.0).contains(&params.lng) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "lat must be within [-90, 90] and lng within [-180, 180]".to_owned(),
+        ));
+    }
+
+    let radius = params.radius.unwrap_or(DEFAULT_NEARBY_RADIUS_METRES);
+    if !(radius > 0.0 && radius <= MAX_NEARBY_RADIUS_METRES) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("radius must be greater than 0 and at most {MAX_NEARBY_RADIUS_METRES} metres"),
+        ));
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_NEARBY_LIMIT);
+    if limit == 0 || limit > MAX_NEARBY_LIMIT {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("limit must be between 1 and {MAX_NEARBY_LIMIT}"),
+        ));
+    }
+
+    let collection = db.collection::<ChangingRoom>("rooms");
+
+    let mut pipeline = vec![doc! {
+        "$geoNear": {
+            "near": { "type": "Point", "coordinates": [params.lng, params.lat] },
+            "key": "locationGeo",
+            "query": only_visible(attributes.apply(doc! {})),
+            "distanceField": "distance",
+            "maxDistance": radius,
+            "spherical": true,
+        }
+    }];
+    // Sorting before limiting gives the best rooms within the radius, not just the best of the closest
+    if let Some(sort) = sort.nearby_sort() {
+        pipeline.push(doc! { "$sort": sort });
+    }
+    pipeline.push(doc! { "$limit": i64::from(limit) });
+
+    let docs: Vec<Document> = collection
+        .aggregate(pipeline)
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get cursor for nearby rooms");
+            GENERIC_DB_ERROR.clone()
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                err = e.to_string(),
+                "Unable to collect nearby rooms into Vec"
+            );
+            GENERIC_DB_ERROR.clone()
+        })?;
+
+    docs.into_iter()
+        .map(|mut doc| {
+            let distance = doc.get_f64("distance").map_err(|e| {
+                tracing::error!(err = e.to_string(), "Nearby room is missing distance");
+                GENERIC_DB_ERROR.clone()
+            })?;
+            doc.remove("distance");
+
+            let room = bson::from_document::<ChangingRoom>(doc).map_err(|e| {
+                tracing::error!(err = e.to_string(), "Unable to deserialize nearby room");
+                GENERIC_DB_ERROR.clone()
+            })?;
+
+            Ok((room, distance))
+        })
+        .collect()
+}
+
 pub async fn get_room_by_id(
     Path(id): Path<String>,
     State(db): State<Database>,
-) -> Result<Json<ChangingRoom>, (StatusCode, String)> {
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     let id = Uuid::parse_str(&id).map_err(|e| {
         tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
         (
@@ -129,10 +439,124 @@ pub async fn get_room_by_id(
     })?;
 
     match result {
-        Some(room) => Ok(Json(room)),
+        Some(room) if room.deleted.is_some() => Err((
+            StatusCode::GONE,
+            format!("Room with id {id} has been deleted"),
+        )),
+        Some(room) => Ok(([(header::ETAG, etag(room.revision))], Json(room))),
         None => Err((
             StatusCode::NOT_FOUND,
             format!("No room found with id {id:?}"),
         )),
     }
 }
+
+/// Adds conditions excluding deleted and hidden rooms to `filter`
+pub fn only_visible(mut filter: Document) -> Document {
+    filter.insert("deleted", Bson::Null);
+    filter.insert("hidden", doc! { "$ne": true });
+    filter
+}
+
+/// The parsed opening hours of a room, if it has any
+fn opening_hours(room: &ChangingRoom) -> Option<OpeningHours> {
+    room.attributes
+        .opening_hours
+        .as_deref()?
+        .parse()
+        .inspect_err(|e: &String| {
+            tracing::debug!(
+                err = e,
+                room_id = room.id.to_string(),
+                "Unable to parse opening hours"
+            );
+        })
+        .ok()
+}
+
+/// The room as a GeoJSON feature, with opening hours evaluated at `now`.
+///
+/// `now` is taken once per request, so every feature in a response agrees on which rooms are open.
+pub fn room_to_feature(r: ChangingRoom, now: DateTime<Utc>) -> Feature {
+    let opening_hours = opening_hours(&r);
+
+    // Properties
+    let mut props = JsonObject::new();
+    props.insert(String::from("name"), Value::String(r.name));
+    props.insert(
+        String::from("ratings"),
+        serde_json::to_value(r.ratings)
+            .inspect_err(|e| {
+                tracing::error!(
+                    err = e.to_string(),
+                    room_id = r.id.to_string(),
+                    "Unable to serialize rating"
+                );
+            })
+            .unwrap(),
+    );
+    if let Some(ext_id) = r.external_id {
+        props.insert(String::from("externalId"), Value::String(ext_id));
+    } else {
+        props.insert(String::from("externalId"), Value::Null);
+    }
+    props.insert(
+        String::from("isOpen"),
+        Value::from(opening_hours.as_ref().map(|oh| oh.is_open(now))),
+    );
+    props.insert(
+        String::from("nextChange"),
+        Value::from(
+            opening_hours
+                .and_then(|oh| oh.next_change(now))
+                .map(|t| t.to_rfc3339()),
+        ),
+    );
+    // Attributes are flattened into the properties, to make them easy to use for map styling
+    match serde_json::to_value(r.attributes) {
+        Ok(Value::Object(attributes)) => props.extend(attributes),
+        Ok(_) => {}
+        Err(e) => tracing::error!(
+            err = e.to_string(),
+            room_id = r.id.to_string(),
+            "Unable to serialize attributes"
+        ),
+    }
+
+    // Geo Feature
+    Feature {
+        bbox: None,
+        geometry: Some(r.location_geo),
+        id: Some(Id::String(r.id.to_string())),
+        properties: Some(props),
+        foreign_members: None,
+    }
+}
+
+fn clustered_to_feature(clustered: Clustered, now: DateTime<Utc>) -> Feature {
+    match clustered {
+        Clustered::Room(room) => room_to_feature(*room, now),
+        Clustered::Cluster {
+            id,
+            location,
+            point_count,
+            average_rating,
+        } => {
+            let mut props = JsonObject::new();
+            props.insert(String::from("cluster"), Value::Bool(true));
+            props.insert(String::from("pointCount"), Value::from(point_count));
+            props.insert(String::from("averageRating"), Value::from(average_rating));
+
+            Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                    location.lng,
+                    location.lat,
+                ]))),
+                id: Some(Id::String(id)),
+                properties: Some(props),
+                foreign_members: None,
+            }
+        }
+    }
+}
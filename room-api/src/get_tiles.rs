@@ -0,0 +1,118 @@
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::Database;
+
+use crate::geo::{BoundingBox, MAX_ZOOM, to_web_mercator};
+use crate::get_rooms::{only_visible, room_to_feature};
+use crate::models::ChangingRoom;
+use crate::mvt::{self, DEFAULT_EXTENT, Layer};
+
+const LAYER_NAME: &str = "rooms";
+
+/// Rooms this close to the tile edge (as a fraction of the tile size) are included in
+/// neighbouring tiles as well, so that symbols aren't cut off at tile boundaries
+const TILE_BUFFER: f64 = 64.0 / 4096.0;
+
+const CACHE_CONTROL: &str = "public, max-age=300";
+const MVT_CONTENT_TYPE: &str = "application/vnd.mapbox-vector-tile";
+
+pub async fn get_tile(
+    Path((z, x, y)): Path<(u8, u32, String)>,
+    State(db): State<Database>,
+) -> Result<Response, (StatusCode, String)> {
+    // The router doesn't support suffixes on path parameters, so the extension is part of `y`
+    let y = y
+        .strip_suffix(".mvt")
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Tiles must end with .mvt".to_owned()))?
+        .parse::<u32>()
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Tile y coordinate must be a positive integer. Inner error: {e}"),
+            )
+        })?;
+
+    if z > MAX_ZOOM {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Tile zoom must be between 0 and {MAX_ZOOM}"),
+        ));
+    }
+
+    let n = 1_u32 << z;
+    if x >= n || y >= n {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Tile x and y must be less than {n} at zoom {z}"),
+        ));
+    }
+
+    let collection = db.collection::<ChangingRoom>("rooms");
+
+    let rooms: Vec<ChangingRoom> = collection
+        .find(only_visible(
+            BoundingBox::of_tile(z, x, y, TILE_BUFFER).to_filter(),
+        ))
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get cursor for tile rooms");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to collect tile rooms into Vec");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        })?;
+
+    if rooms.is_empty() {
+        return Ok((
+            StatusCode::NO_CONTENT,
+            [(header::CACHE_CONTROL, CACHE_CONTROL)],
+        )
+            .into_response());
+    }
+
+    let mut layer = Layer::new(LAYER_NAME, DEFAULT_EXTENT);
+    let tile_size = f64::from(n);
+    let extent = f64::from(DEFAULT_EXTENT);
+    let now = Utc::now();
+
+    for room in rooms {
+        let (world_x, world_y) = to_web_mercator(room.location);
+        let tile_x = ((world_x * tile_size - f64::from(x)) * extent).round() as i32;
+        let tile_y = ((world_y * tile_size - f64::from(y)) * extent).round() as i32;
+
+        let mut feature = room_to_feature(room, now);
+        if let (Some(props), Some(id)) = (feature.properties.as_mut(), feature.id.take()) {
+            // Vector tile feature ids must be integers, so the room id goes in the properties
+            props.insert(
+                String::from("id"),
+                serde_json::to_value(id).unwrap_or_default(),
+            );
+        }
+
+        layer.add_point(tile_x, tile_y, &feature.properties.unwrap_or_default());
+    }
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, MVT_CONTENT_TYPE),
+            (header::CACHE_CONTROL, CACHE_CONTROL),
+        ],
+        mvt::encode_tile(&[layer]),
+    )
+        .into_response())
+}
@@ -0,0 +1,108 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    ClientSession, Database,
+    bson::{Uuid, doc},
+};
+
+use crate::auth::User;
+use crate::models::{ChangeSource, ChangingRoom, RoomChange, RoomHistoryEntry};
+
+pub const HISTORY_COLLECTION: &str = "room_history";
+
+/// Starts a transaction for changing a room together with recording the change
+pub async fn start_transaction(db: &Database) -> Result<ClientSession, mongodb::error::Error> {
+    let mut session = db.client().start_session().await?;
+    session.start_transaction().await?;
+    Ok(session)
+}
+
+/// Records a change made through the api in the room history.
+///
+/// Runs in the transaction in `session` together with the change itself,
+/// so that a change is never saved without its history entry.
+pub async fn record_change(
+    db: &Database,
+    session: &mut ClientSession,
+    action: RoomChange,
+    user: &User,
+    before: Option<ChangingRoom>,
+    after: ChangingRoom,
+) -> Result<(), mongodb::error::Error> {
+    let entry = RoomHistoryEntry::new(
+        action,
+        ChangeSource::Api,
+        user.subject.clone(),
+        user.name.clone(),
+        before,
+        after,
+    );
+
+    db.collection::<RoomHistoryEntry>(HISTORY_COLLECTION)
+        .insert_one(&entry)
+        .session(session)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_room_history(
+    _user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<Json<Vec<RoomHistoryEntry>>, (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let room = db
+        .collection::<ChangingRoom>("rooms")
+        .find_one(doc! { "id": id })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get room by id from db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        })?;
+
+    if room.is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("No room found with id {id}")));
+    }
+
+    let history = db
+        .collection::<RoomHistoryEntry>(HISTORY_COLLECTION)
+        .find(doc! { "roomId": id })
+        .sort(doc! { "revision": 1, "changedAt": 1 })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get cursor for room history");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                err = e.to_string(),
+                "Unable to collect room history into Vec"
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        })?;
+
+    Ok(Json(history))
+}
@@ -0,0 +1,205 @@
+//! Validation of bearer tokens (JWTs) issued by our identity provider.
+//!
+//! Shared between room-api and review-api: review-api/src/jwt.rs is a symlink to this file,
+//! the same way osm-sync shares models.rs. It must compile with both crates' editions and dependencies.
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
+
+/// Validates tokens against the issuers signing keys, which are refetched when they rotate
+#[derive(Debug)]
+pub struct TokenValidator {
+    config: Option<JwtConfig>,
+    keys: RwLock<JwkSet>,
+    keys_fetched_at: RwLock<Instant>,
+}
+
+/// Keys are not refetched more often than this, even if tokens with unknown key ids show up
+const MIN_JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+struct JwtConfig {
+    issuer: String,
+    audience: Option<String>,
+    jwks_source: String,
+    roles_claim: String,
+}
+
+/// The claims we use from a valid token
+#[derive(Clone, Debug)]
+pub struct Claims {
+    pub subject: String,
+    pub name: Option<String>,
+    roles: Vec<String>,
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+impl TokenValidator {
+    /// Configures token validation from env vars starting with `prefix`, e.g. `ROOM_API`.
+    ///
+    /// `{prefix}_JWKS` can be either a URL or a path to a local file.
+    /// If `{prefix}_JWT_ISSUER` or `{prefix}_JWKS` is not set, every token is rejected.
+    pub async fn from_env(prefix: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (Ok(issuer), Ok(jwks_source)) = (
+            env::var(format!("{prefix}_JWT_ISSUER")),
+            env::var(format!("{prefix}_JWKS")),
+        ) else {
+            tracing::warn!(
+                "{prefix}_JWT_ISSUER or {prefix}_JWKS not set. Requests with bearer tokens will be rejected"
+            );
+            return Ok(TokenValidator {
+                config: None,
+                keys: RwLock::new(JwkSet { keys: Vec::new() }),
+                keys_fetched_at: RwLock::new(Instant::now()),
+            });
+        };
+
+        let config = JwtConfig {
+            issuer,
+            audience: env::var(format!("{prefix}_JWT_AUDIENCE")).ok(),
+            jwks_source,
+            roles_claim: env::var(format!("{prefix}_JWT_ROLES_CLAIM"))
+                .unwrap_or("roles".to_owned()),
+        };
+
+        let keys = fetch_jwks(&config.jwks_source).await?;
+        tracing::info!(
+            issuer = config.issuer,
+            key_count = keys.keys.len(),
+            "Loaded JWKS from {}",
+            config.jwks_source
+        );
+
+        Ok(TokenValidator {
+            config: Some(config),
+            keys: RwLock::new(keys),
+            keys_fetched_at: RwLock::new(Instant::now()),
+        })
+    }
+
+    pub async fn validate(&self, token: &str) -> Result<Claims, String> {
+        let config = self
+            .config
+            .as_ref()
+            .ok_or("Authentication is not configured")?;
+
+        let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+        if !matches!(
+            header.alg,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+                | Algorithm::ES256
+                | Algorithm::ES384
+                | Algorithm::EdDSA
+        ) {
+            return Err(format!("Unsupported token algorithm {:?}", header.alg));
+        }
+
+        let jwk = match self.find_key(header.kid.as_deref()).await {
+            Some(jwk) => jwk,
+            None if is_url(&config.jwks_source) => {
+                // The identity provider might have rotated its keys
+                self.refresh_keys(config).await?;
+                self.find_key(header.kid.as_deref())
+                    .await
+                    .ok_or("No matching key found for token")?
+            }
+            None => return Err("No matching key found for token".to_owned()),
+        };
+
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&config.issuer]);
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or("Token is missing sub claim")?
+            .to_owned();
+
+        let name = claims.get("name").and_then(Value::as_str).map(String::from);
+
+        let roles = match claims.get(&config.roles_claim) {
+            Some(Value::String(role)) => vec![role.clone()],
+            Some(Value::Array(roles)) => roles
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(Claims {
+            subject,
+            name,
+            roles,
+        })
+    }
+
+    async fn find_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let keys = self.keys.read().await;
+        match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        }
+    }
+
+    async fn refresh_keys(&self, config: &JwtConfig) -> Result<(), String> {
+        let mut fetched_at = self.keys_fetched_at.write().await;
+        if fetched_at.elapsed() < MIN_JWKS_REFRESH_INTERVAL {
+            return Ok(());
+        }
+        *fetched_at = Instant::now();
+
+        let keys = fetch_jwks(&config.jwks_source).await.map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to refresh JWKS");
+            "Unable to refresh signing keys".to_owned()
+        })?;
+        *self.keys.write().await = keys;
+        Ok(())
+    }
+}
+
+async fn fetch_jwks(source: &str) -> Result<JwkSet, Box<dyn std::error::Error>> {
+    if is_url(source) {
+        Ok(reqwest::get(source)
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?)
+    } else {
+        Ok(serde_json::from_str(
+            &tokio::fs::read_to_string(source).await?,
+        )?)
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
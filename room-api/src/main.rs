@@ -1,31 +1,82 @@
 use std::env;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
+use axum::extract::FromRef;
 use axum::http::{self, Method};
-use axum::{routing, Router};
+use axum::{Router, routing};
+use mongodb::bson::doc;
 use mongodb::options::ClientOptions;
-use mongodb::{Client, Database};
+use mongodb::{Client, Database, IndexModel};
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 
+use crate::auth::Authenticator;
 use crate::create_room::create_room;
 use crate::delete_room::delete_room;
-use crate::get_rooms::{get_all_rooms, get_all_rooms_v2, get_room_by_id};
+use crate::get_rooms::{
+    get_all_rooms, get_all_rooms_v2, get_nearby_rooms, get_nearby_rooms_v2, get_room_by_id,
+};
+use crate::get_tiles::get_tile;
 use crate::healthcheck::{live, ready};
+use crate::history::{HISTORY_COLLECTION, get_room_history};
+use crate::models::{ChangingRoom, RoomHistoryEntry};
+use crate::patch_room::patch_room;
+use crate::reports::{
+    REPORTS_COLLECTION, RoomReport, get_open_reports, report_room, resolve_reports,
+};
+use crate::restore_room::restore_room;
+use crate::rollback_room::rollback_room;
+use crate::update_ratings::update_room_ratings;
 use crate::update_room::update_room;
 
+mod auth;
+mod cluster;
 mod create_room;
 mod delete_room;
+mod geo;
 mod get_rooms;
+mod get_tiles;
 mod healthcheck;
+mod history;
+mod jwt;
 mod models;
+mod mvt;
+mod opening_hours;
+mod patch_room;
+mod reports;
+mod restore_room;
+mod revision;
+mod rollback_room;
+mod update_ratings;
 mod update_room;
 
+#[derive(Clone)]
+struct AppState {
+    db: Database,
+    auth: Arc<Authenticator>,
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Authenticator> {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.clone()
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
     let db = get_db_handle().await?;
+    ensure_db_ix(&db).await?;
+    migrate_db(&db).await?;
+    let auth = Arc::new(Authenticator::from_env().await?);
 
     let app = Router::new()
         .route("/readyz", routing::get(ready))
@@ -33,9 +84,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/rooms", routing::post(create_room))
         .route("/rooms", routing::get(get_all_rooms))
         .route("/rooms-v2", routing::get(get_all_rooms_v2))
+        .route("/rooms/near", routing::get(get_nearby_rooms))
+        .route("/rooms-v2/near", routing::get(get_nearby_rooms_v2))
         .route("/rooms/{id}", routing::get(get_room_by_id))
+        .route("/tiles/{z}/{x}/{y}", routing::get(get_tile))
         .route("/rooms/{id}", routing::put(update_room))
+        .route("/rooms/{id}", routing::patch(patch_room))
         .route("/rooms/{id}", routing::delete(delete_room))
+        .route("/rooms/{id}/restore", routing::post(restore_room))
+        .route("/rooms/{id}/history", routing::get(get_room_history))
+        .route("/rooms/{id}/rollback", routing::post(rollback_room))
+        .route("/rooms/{id}/ratings", routing::put(update_room_ratings))
+        .route("/rooms/{id}/reports", routing::post(report_room))
+        .route(
+            "/rooms/{id}/reports/resolve",
+            routing::post(resolve_reports),
+        )
+        .route("/reports", routing::get(get_open_reports))
         .layer(
             CorsLayer::new()
                 .allow_origin([
@@ -44,10 +109,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "https://dev.stellerom.no".parse()?,
                     "https://www.stellerom.no".parse()?,
                 ])
-                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([http::header::CONTENT_TYPE]),
+                .allow_methods([
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::DELETE,
+                ])
+                .allow_headers([
+                    http::header::CONTENT_TYPE,
+                    http::header::AUTHORIZATION,
+                    http::header::IF_MATCH,
+                ])
+                .expose_headers([http::header::ETAG]),
         )
-        .with_state(db);
+        .with_state(AppState { db, auth });
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     let listener = TcpListener::bind(addr).await?;
@@ -85,3 +161,69 @@ async fn get_db_handle() -> Result<Database, mongodb::error::Error> {
 
     Ok(mongo_client.database(&db_name))
 }
+
+async fn ensure_db_ix(db: &Database) -> Result<(), mongodb::error::Error> {
+    let ix = IndexModel::builder()
+        .keys(doc! { "locationGeo": "2dsphere" })
+        .build();
+
+    let ix = db
+        .collection::<ChangingRoom>("rooms")
+        .create_index(ix)
+        .await?;
+    tracing::info!("Created index {} (or verified existence)", ix.index_name);
+
+    // bbox and tile queries compare plain latitude and longitude ranges
+    let location_ix = IndexModel::builder()
+        .keys(doc! { "location.lng": 1, "location.lat": 1 })
+        .build();
+
+    let location_ix = db
+        .collection::<ChangingRoom>("rooms")
+        .create_index(location_ix)
+        .await?;
+    tracing::info!(
+        "Created index {} (or verified existence)",
+        location_ix.index_name
+    );
+
+    let history_ix = IndexModel::builder()
+        .keys(doc! { "roomId": 1, "revision": 1 })
+        .build();
+
+    let history_ix = db
+        .collection::<RoomHistoryEntry>(HISTORY_COLLECTION)
+        .create_index(history_ix)
+        .await?;
+    tracing::info!(
+        "Created index {} (or verified existence)",
+        history_ix.index_name
+    );
+
+    let reports_ix = IndexModel::builder().keys(doc! { "roomId": 1 }).build();
+
+    let reports_ix = db
+        .collection::<RoomReport>(REPORTS_COLLECTION)
+        .create_index(reports_ix)
+        .await?;
+    tracing::info!(
+        "Created index {} (or verified existence)",
+        reports_ix.index_name
+    );
+    Ok(())
+}
+
+/// Backfills fields added to rooms after they were first created
+async fn migrate_db(db: &Database) -> Result<(), mongodb::error::Error> {
+    let res = db
+        .collection::<ChangingRoom>("rooms")
+        .update_many(
+            doc! { "revision": { "$exists": false } },
+            doc! { "$set": { "revision": 0 } },
+        )
+        .await?;
+    if res.modified_count > 0 {
+        tracing::info!("Set initial revision on {} rooms", res.modified_count);
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+
 use bounded_integer::BoundedU8;
+use chrono::{DateTime, Utc};
 use geojson::Geometry;
 use mongodb::bson::Uuid;
 use serde::{Deserialize, Serialize};
@@ -6,6 +9,9 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChangingRoom {
     pub id: Uuid,
+    /// Incremented on every change. Exposed as ETag for optimistic concurrency
+    #[serde(default)]
+    pub revision: i64,
     pub name: String,
     pub location: Location,
     #[serde(rename = "locationGeo")]
@@ -13,19 +19,172 @@ pub struct ChangingRoom {
     pub ratings: Option<Ratings>,
     #[serde(rename = "externalId")]
     pub external_id: Option<String>,
+    #[serde(default)]
+    pub attributes: Attributes,
+    /// Tags of the OpenStreetMap node the room was imported from, as is
+    #[serde(rename = "osmTags", default)]
+    pub osm_tags: Option<HashMap<String, String>>,
+    /// Set when the room has been deleted. Deleted rooms are kept as tombstones so they can be restored,
+    /// and so osm-sync doesn't re-create them
+    #[serde(default)]
+    pub deleted: Option<Tombstone>,
+    /// Set when the room has been reported by enough users. Hidden rooms are left out of listings
+    /// until an admin has resolved the reports
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+/// Facts about a room which parents want to know before visiting. None means unknown
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attributes {
+    /// Accessible for wheelchairs
+    pub wheelchair: Option<bool>,
+    /// Where the room is. Can be several places, e.g. both the women's and the men's toilets
+    #[serde(default)]
+    pub placement: Vec<Placement>,
+    /// Whether a fee is required
+    pub fee: Option<bool>,
+    /// In OpenStreetMap `opening_hours` format, e.g. `Mo-Fr 08:00-20:00`
+    #[serde(rename = "openingHours")]
+    pub opening_hours: Option<String>,
+    pub indoor: Option<bool>,
+    /// Floor of the building, e.g. `0` for the ground floor or `-1` for the basement
+    pub level: Option<String>,
+    pub sink: Option<bool>,
+    #[serde(rename = "nursingArea")]
+    pub nursing_area: Option<bool>,
+    #[serde(rename = "diapersForSale")]
+    pub diapers_for_sale: Option<bool>,
+    /// The business or organisation running the room
+    pub operator: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Placement {
+    GenderNeutral,
+    Womens,
+    Mens,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tombstone {
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: DateTime<Utc>,
+    #[serde(rename = "deletedBy")]
+    pub deleted_by: String,
+    pub reason: Option<String>,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     pub lat: f64,
     pub lng: f64,
 }
 
+/// Ratings computed from the reviews of a room by review-api
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ratings {
+    /// Averages rounded to whole stars, kept for older clients
     pub availability: StarRating,
     pub safety: StarRating,
     pub cleanliness: StarRating,
+    /// Number of reviews the ratings are computed from.
+    /// This and the fields below are None for ratings from before they were tracked
+    #[serde(default)]
+    pub count: Option<u32>,
+    #[serde(default)]
+    pub averages: Option<RatingAverages>,
+    /// Averages where newer reviews weigh more. None unless review-api is configured with a half-life
+    #[serde(rename = "recentAverages", default)]
+    pub recent_averages: Option<RatingAverages>,
+    #[serde(default)]
+    pub histograms: Option<RatingHistograms>,
+    /// For ranking rooms. A Bayesian average which pulls rooms with few reviews towards a prior mean
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// Like `score`, but with newer reviews weighing more. None unless review-api is configured with a half-life
+    #[serde(rename = "recentScore", default)]
+    pub recent_score: Option<f64>,
+    #[serde(rename = "lastReviewedAt", default)]
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RatingAverages {
+    pub availability: f64,
+    pub safety: f64,
+    pub cleanliness: f64,
+}
+
+/// Number of reviews giving 1 to 5 stars, for each category
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RatingHistograms {
+    pub availability: [u32; 5],
+    pub safety: [u32; 5],
+    pub cleanliness: [u32; 5],
 }
 
 pub type StarRating = BoundedU8<1, 5>;
+
+/// A change to a room, stored in the `room_history` collection
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomHistoryEntry {
+    pub id: Uuid,
+    #[serde(rename = "roomId")]
+    pub room_id: Uuid,
+    /// Revision of the room after the change
+    pub revision: i64,
+    pub action: RoomChange,
+    pub source: ChangeSource,
+    /// Subject of the user making the change, or the name of the job for automated changes
+    pub actor: String,
+    #[serde(rename = "actorName")]
+    pub actor_name: Option<String>,
+    #[serde(rename = "changedAt")]
+    pub changed_at: DateTime<Utc>,
+    /// The room before the change. None for newly created rooms
+    pub before: Option<ChangingRoom>,
+    pub after: ChangingRoom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomChange {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Rollback,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeSource {
+    Api,
+    OsmSync,
+}
+
+impl RoomHistoryEntry {
+    pub fn new(
+        action: RoomChange,
+        source: ChangeSource,
+        actor: String,
+        actor_name: Option<String>,
+        before: Option<ChangingRoom>,
+        after: ChangingRoom,
+    ) -> Self {
+        RoomHistoryEntry {
+            id: Uuid::new(),
+            room_id: after.id,
+            revision: after.revision,
+            action,
+            source,
+            actor,
+            actor_name,
+            changed_at: Utc::now(),
+            before,
+            after,
+        }
+    }
+}
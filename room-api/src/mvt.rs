@@ -0,0 +1,399 @@
+//! Minimal encoder for Mapbox Vector Tiles (point layers only).
+//!
+//! See <https://github.com/mapbox/vector-tile-spec/tree/master/2.1> for the format.
+
+use std::collections::HashMap;
+
+use geojson::JsonObject;
+use serde_json::Value;
+
+pub const DEFAULT_EXTENT: u32 = 4096;
+
+const MVT_VERSION: u32 = 2;
+const GEOM_TYPE_POINT: u32 = 1;
+const CMD_MOVE_TO: u32 = 1;
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_64BIT: u32 = 1;
+const WIRE_LEN: u32 = 2;
+
+#[derive(Debug)]
+pub struct Layer {
+    name: String,
+    extent: u32,
+    keys: Vec<String>,
+    key_ix: HashMap<String, u32>,
+    values: Vec<Vec<u8>>,
+    value_ix: HashMap<Vec<u8>, u32>,
+    features: Vec<Vec<u8>>,
+}
+
+impl Layer {
+    pub fn new(name: &str, extent: u32) -> Self {
+        Layer {
+            name: name.to_owned(),
+            extent,
+            keys: Vec::new(),
+            key_ix: HashMap::new(),
+            values: Vec::new(),
+            value_ix: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Adds a point feature at tile coordinates `x`, `y`.
+    ///
+    /// Nested objects and arrays are stored as JSON strings, and null properties are left out,
+    /// since vector tiles only support scalar values.
+    pub fn add_point(&mut self, x: i32, y: i32, props: &JsonObject) {
+        let mut tags = Vec::with_capacity(props.len() * 2);
+        for (key, value) in props {
+            let Some(value) = encode_value(value) else {
+                continue;
+            };
+            tags.push(self.key_index(key));
+            tags.push(self.value_index(value));
+        }
+
+        let geometry = [command(CMD_MOVE_TO, 1), zigzag(x), zigzag(y)];
+
+        let mut feature = Vec::new();
+        write_packed(&mut feature, 2, &tags);
+        write_varint_field(&mut feature, 3, u64::from(GEOM_TYPE_POINT));
+        write_packed(&mut feature, 4, &geometry);
+        self.features.push(feature);
+    }
+
+    fn key_index(&mut self, key: &str) -> u32 {
+        if let Some(ix) = self.key_ix.get(key) {
+            return *ix;
+        }
+        let ix = self.keys.len() as u32;
+        self.keys.push(key.to_owned());
+        self.key_ix.insert(key.to_owned(), ix);
+        ix
+    }
+
+    fn value_index(&mut self, value: Vec<u8>) -> u32 {
+        if let Some(ix) = self.value_ix.get(&value) {
+            return *ix;
+        }
+        let ix = self.values.len() as u32;
+        self.values.push(value.clone());
+        self.value_ix.insert(value, ix);
+        ix
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint_field(&mut buf, 15, u64::from(MVT_VERSION));
+        write_len_field(&mut buf, 1, self.name.as_bytes());
+        for feature in &self.features {
+            write_len_field(&mut buf, 2, feature);
+        }
+        for key in &self.keys {
+            write_len_field(&mut buf, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            write_len_field(&mut buf, 4, value);
+        }
+        write_varint_field(&mut buf, 5, u64::from(self.extent));
+        buf
+    }
+}
+
+/// Encodes layers into a tile. Empty layers are left out.
+pub fn encode_tile(layers: &[Layer]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for layer in layers.iter().filter(|l| !l.is_empty()) {
+        write_len_field(&mut buf, 3, &layer.encode());
+    }
+    buf
+}
+
+/// Encodes a JSON value as a vector tile `Value` message
+fn encode_value(value: &Value) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    match value {
+        Value::Null => return None,
+        Value::Bool(b) => write_varint_field(&mut buf, 7, u64::from(*b)),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                write_varint_field(&mut buf, 5, u);
+            } else if let Some(i) = n.as_i64() {
+                write_varint_field(&mut buf, 6, ((i << 1) ^ (i >> 63)) as u64);
+            } else {
+                write_key(&mut buf, 3, WIRE_64BIT);
+                buf.extend_from_slice(&n.as_f64().unwrap_or_default().to_le_bytes());
+            }
+        }
+        Value::String(s) => write_len_field(&mut buf, 1, s.as_bytes()),
+        Value::Array(_) | Value::Object(_) => {
+            write_len_field(&mut buf, 1, value.to_string().as_bytes())
+        }
+    }
+    Some(buf)
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buf, u64::from((field << 3) | wire_type));
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_key(buf, field, WIRE_VARINT);
+    write_varint(buf, value);
+}
+
+fn write_len_field(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buf, field, WIRE_LEN);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::new();
+    for v in values {
+        write_varint(&mut packed, u64::from(*v));
+    }
+    write_len_field(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use serde_json::json;
+
+    // Messages from vector_tile.proto in the vector tile spec, for decoding what we encode
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Tile {
+        #[prost(message, repeated, tag = "3")]
+        layers: Vec<TileLayer>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct TileLayer {
+        #[prost(uint32, tag = "15")]
+        version: u32,
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(message, repeated, tag = "2")]
+        features: Vec<TileFeature>,
+        #[prost(string, repeated, tag = "3")]
+        keys: Vec<String>,
+        #[prost(message, repeated, tag = "4")]
+        values: Vec<TileValue>,
+        #[prost(uint32, optional, tag = "5")]
+        extent: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct TileFeature {
+        #[prost(uint32, repeated, tag = "2")]
+        tags: Vec<u32>,
+        #[prost(uint32, optional, tag = "3")]
+        r#type: Option<u32>,
+        #[prost(uint32, repeated, tag = "4")]
+        geometry: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct TileValue {
+        #[prost(string, optional, tag = "1")]
+        string_value: Option<String>,
+        #[prost(double, optional, tag = "3")]
+        double_value: Option<f64>,
+        #[prost(uint64, optional, tag = "5")]
+        uint_value: Option<u64>,
+        #[prost(sint64, optional, tag = "6")]
+        sint_value: Option<i64>,
+        #[prost(bool, optional, tag = "7")]
+        bool_value: Option<bool>,
+    }
+
+    fn decode(layers: &[Layer]) -> Tile {
+        Tile::decode(encode_tile(layers).as_slice()).expect("tile should decode")
+    }
+
+    /// The properties of a feature, resolved from its tags
+    fn properties<'a>(
+        layer: &'a TileLayer,
+        feature: &TileFeature,
+    ) -> Vec<(&'a str, &'a TileValue)> {
+        feature
+            .tags
+            .chunks(2)
+            .map(|tag| {
+                (
+                    layer.keys[tag[0] as usize].as_str(),
+                    &layer.values[tag[1] as usize],
+                )
+            })
+            .collect()
+    }
+
+    fn string(s: &str) -> TileValue {
+        TileValue {
+            string_value: Some(s.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_layers_are_left_out() {
+        let tile = decode(&[Layer::new("rooms", DEFAULT_EXTENT)]);
+        assert!(tile.layers.is_empty());
+    }
+
+    #[test]
+    fn layer_metadata_round_trips() {
+        let mut layer = Layer::new("rooms", 512);
+        layer.add_point(1, 2, &JsonObject::new());
+
+        let tile = decode(&[layer]);
+        assert_eq!(tile.layers.len(), 1);
+        assert_eq!(tile.layers[0].version, MVT_VERSION);
+        assert_eq!(tile.layers[0].name, "rooms");
+        assert_eq!(tile.layers[0].extent, Some(512));
+    }
+
+    #[test]
+    fn point_geometry_round_trips() {
+        let cases = [(0, 0), (25, 17), (4095, 4095), (-64, -1), (4200, -300)];
+
+        let mut layer = Layer::new("rooms", DEFAULT_EXTENT);
+        for (x, y) in cases {
+            layer.add_point(x, y, &JsonObject::new());
+        }
+
+        let tile = decode(&[layer]);
+        let features = &tile.layers[0].features;
+        assert_eq!(features.len(), cases.len());
+
+        for (feature, (x, y)) in features.iter().zip(cases) {
+            assert_eq!(feature.r#type, Some(GEOM_TYPE_POINT));
+            let [cmd, dx, dy] = feature.geometry[..] else {
+                panic!("expected a single MoveTo, got {:?}", feature.geometry);
+            };
+            assert_eq!(cmd & 0x7, CMD_MOVE_TO);
+            assert_eq!(cmd >> 3, 1);
+            let unzigzag = |n: u32| ((n >> 1) as i32) ^ -((n & 1) as i32);
+            assert_eq!((unzigzag(dx), unzigzag(dy)), (x, y));
+        }
+    }
+
+    #[test]
+    fn property_values_round_trip() {
+        let props = json!({
+            "name": "Stellerom",
+            "isOpen": true,
+            "count": 12,
+            "offset": -3,
+            "score": 4.25,
+            "nextChange": null,
+            "attributes": { "sink": true },
+        });
+
+        let mut layer = Layer::new("rooms", DEFAULT_EXTENT);
+        layer.add_point(0, 0, props.as_object().unwrap());
+
+        let tile = decode(&[layer]);
+        let layer = &tile.layers[0];
+        let mut decoded = properties(layer, &layer.features[0]);
+        decoded.sort_by_key(|(key, _)| *key);
+
+        let expected = [
+            ("attributes", string(r#"{"sink":true}"#)),
+            (
+                "count",
+                TileValue {
+                    uint_value: Some(12),
+                    ..Default::default()
+                },
+            ),
+            (
+                "isOpen",
+                TileValue {
+                    bool_value: Some(true),
+                    ..Default::default()
+                },
+            ),
+            ("name", string("Stellerom")),
+            (
+                "offset",
+                TileValue {
+                    sint_value: Some(-3),
+                    ..Default::default()
+                },
+            ),
+            (
+                "score",
+                TileValue {
+                    double_value: Some(4.25),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        assert_eq!(
+            decoded.len(),
+            expected.len(),
+            "null properties are left out"
+        );
+        for ((key, value), (expected_key, expected_value)) in decoded.into_iter().zip(&expected) {
+            assert_eq!(key, *expected_key);
+            assert_eq!(value, expected_value);
+        }
+    }
+
+    #[test]
+    fn keys_and_values_are_shared_between_features() {
+        let mut layer = Layer::new("rooms", DEFAULT_EXTENT);
+        layer.add_point(
+            0,
+            0,
+            json!({ "fee": false, "name": "A" }).as_object().unwrap(),
+        );
+        layer.add_point(
+            1,
+            1,
+            json!({ "fee": false, "name": "B" }).as_object().unwrap(),
+        );
+
+        let tile = decode(&[layer]);
+        let layer = &tile.layers[0];
+        assert_eq!(layer.keys, ["fee", "name"]);
+        assert_eq!(layer.values.len(), 3);
+
+        let second = properties(layer, &layer.features[1]);
+        assert!(second.contains(&("name", &string("B"))));
+        assert!(second.contains(&(
+            "fee",
+            &TileValue {
+                bool_value: Some(false),
+                ..Default::default()
+            }
+        )));
+    }
+}
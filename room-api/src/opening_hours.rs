@@ -0,0 +1,619 @@
+//! Parsing and evaluation of OpenStreetMap `opening_hours` values.
+//!
+//! Supports the commonly used subset of <https://wiki.openstreetmap.org/wiki/Key:opening_hours>:
+//! weekdays, public holidays (`PH`), time spans, `off`/`closed` and `24/7`.
+//! Everything is evaluated in Norwegian local time, with Norwegian public holidays.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Europe::Oslo;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// How many days ahead to look for the next change. More than a week, so every weekly rule is covered
+const NEXT_CHANGE_HORIZON_DAYS: i64 = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpeningHours {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    /// None if the rule applies to every day
+    days: Option<DaySelector>,
+    /// Open time spans in minutes since midnight. Spans past midnight end after `MINUTES_PER_DAY`
+    spans: Vec<(u32, u32)>,
+    /// Rules separated by `,` add to the rules before them instead of replacing them
+    additive: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DaySelector {
+    /// Indexed from Monday
+    weekdays: [bool; 7],
+    holidays: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Weekday(usize),
+    Holiday,
+    /// Minutes since midnight
+    Time(u32),
+    Dash,
+    Comma,
+    Semicolon,
+    Off,
+    Open,
+    AlwaysOpen,
+}
+
+impl OpeningHours {
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&Oslo).naive_local();
+        self.local_intervals(local.date(), 0)
+            .iter()
+            .any(|(start, end)| *start <= local && local < *end)
+    }
+
+    /// When the room opens or closes next, after `at`.
+    ///
+    /// None if it doesn't change within the next week, e.g. for rooms which are always open.
+    pub fn next_change(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = at.with_timezone(&Oslo).naive_local();
+        let horizon =
+            (local.date() + Duration::days(NEXT_CHANGE_HORIZON_DAYS + 1)).and_time(NaiveTime::MIN);
+
+        let next = self
+            .local_intervals(local.date(), NEXT_CHANGE_HORIZON_DAYS)
+            .into_iter()
+            .find_map(|(start, end)| {
+                if start <= local && local < end {
+                    Some(end)
+                } else if start > local {
+                    Some(start)
+                } else {
+                    None
+                }
+            })?;
+
+        if next >= horizon {
+            return None;
+        }
+        to_utc(next)
+    }
+
+    /// Open intervals in local time from the day before `from` until `days` days after it,
+    /// sorted and with overlapping intervals merged
+    fn local_intervals(&self, from: NaiveDate, days: i64) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let mut intervals = Vec::new();
+        for offset in -1..=days {
+            let date = from + Duration::days(offset);
+            let midnight = date.and_time(NaiveTime::MIN);
+            for (start, end) in self.spans_on(date) {
+                intervals.push((
+                    midnight + Duration::minutes(i64::from(start)),
+                    midnight + Duration::minutes(i64::from(end)),
+                ));
+            }
+        }
+        intervals.sort();
+
+        let mut merged: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+        for (start, end) in intervals {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+
+    /// Open time spans on the given date, from the rules which apply to it
+    fn spans_on(&self, date: NaiveDate) -> Vec<(u32, u32)> {
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        let holiday = is_public_holiday(date);
+
+        let mut spans = Vec::new();
+        for rule in &self.rules {
+            let applies = match &rule.days {
+                None => true,
+                Some(days) => days.weekdays[weekday] || (days.holidays && holiday),
+            };
+            if !applies {
+                continue;
+            }
+
+            if rule.additive && !rule.spans.is_empty() {
+                spans.extend_from_slice(&rule.spans);
+            } else {
+                spans = rule.spans.clone();
+            }
+        }
+        spans
+    }
+}
+
+impl FromStr for OpeningHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut rules = Vec::new();
+        let mut pos = 0;
+        let mut additive = false;
+
+        while pos < tokens.len() {
+            rules.push(parse_rule(&tokens, &mut pos, additive)?);
+            match tokens.get(pos) {
+                None => break,
+                Some(Token::Semicolon) => additive = false,
+                Some(Token::Comma) => additive = true,
+                Some(t) => return Err(format!("Unexpected {t:?} after rule")),
+            }
+            pos += 1;
+        }
+
+        if rules.is_empty() {
+            return Err("Opening hours are empty".to_owned());
+        }
+        Ok(OpeningHours { rules })
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim();
+
+    while !rest.is_empty() {
+        let (token, len) = if rest.starts_with("24/7") {
+            (Token::AlwaysOpen, 4)
+        } else if rest.starts_with("||") {
+            // Fallback rules are treated as normal rules, which is right for the common `|| off`
+            (Token::Semicolon, 2)
+        } else if rest.starts_with(';') {
+            (Token::Semicolon, 1)
+        } else if rest.starts_with(',') {
+            (Token::Comma, 1)
+        } else if rest.starts_with('-') {
+            (Token::Dash, 1)
+        } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != ':')
+                .unwrap_or(rest.len());
+            (Token::Time(parse_time(&rest[..len])?), len)
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let token = match &rest[..len] {
+                "Mo" => Token::Weekday(0),
+                "Tu" => Token::Weekday(1),
+                "We" => Token::Weekday(2),
+                "Th" => Token::Weekday(3),
+                "Fr" => Token::Weekday(4),
+                "Sa" => Token::Weekday(5),
+                "Su" => Token::Weekday(6),
+                "PH" => Token::Holiday,
+                "off" | "closed" => Token::Off,
+                "open" => Token::Open,
+                word => return Err(format!("Unsupported selector {word}")),
+            };
+            (token, len)
+        } else {
+            return Err(format!("Unexpected character in {rest}"));
+        };
+
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses `HH:MM` into minutes since midnight. Hours up to 48 are allowed for spans past midnight
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (hours, minutes) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid time {s}. Must be HH:MM"))?;
+    let hours = hours
+        .parse::<u32>()
+        .map_err(|e| format!("Invalid time {s}. {e}"))?;
+    let minutes = minutes
+        .parse::<u32>()
+        .map_err(|e| format!("Invalid time {s}. {e}"))?;
+
+    if hours > 48 || minutes > 59 || hours * 60 + minutes > 2 * MINUTES_PER_DAY {
+        return Err(format!("Invalid time {s}"));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+fn parse_rule(tokens: &[Token], pos: &mut usize, additive: bool) -> Result<Rule, String> {
+    if tokens.get(*pos) == Some(&Token::AlwaysOpen) {
+        *pos += 1;
+        return Ok(Rule {
+            days: None,
+            spans: vec![(0, MINUTES_PER_DAY)],
+            additive,
+        });
+    }
+
+    let start = *pos;
+
+    let mut days: Option<DaySelector> = None;
+    while let Some(&token @ (Token::Weekday(_) | Token::Holiday)) = tokens.get(*pos) {
+        let selector = days.get_or_insert_with(DaySelector::default);
+        *pos += 1;
+        match token {
+            Token::Weekday(from) if tokens.get(*pos) == Some(&Token::Dash) => {
+                let Some(Token::Weekday(to)) = tokens.get(*pos + 1) else {
+                    return Err("Weekday ranges must end with a weekday".to_owned());
+                };
+                *pos += 2;
+                // Ranges can wrap around the end of the week, e.g. Sa-Mo
+                let mut day = from;
+                loop {
+                    selector.weekdays[day] = true;
+                    if day == *to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            Token::Weekday(day) => selector.weekdays[day] = true,
+            _ => selector.holidays = true,
+        }
+
+        match (tokens.get(*pos), tokens.get(*pos + 1)) {
+            (Some(Token::Comma), Some(Token::Weekday(_) | Token::Holiday)) => *pos += 1,
+            _ => break,
+        }
+    }
+
+    let mut spans = Vec::new();
+    while let Some(&Token::Time(from)) = tokens.get(*pos) {
+        let (Some(Token::Dash), Some(&Token::Time(to))) =
+            (tokens.get(*pos + 1), tokens.get(*pos + 2))
+        else {
+            return Err("Times must be given as spans, e.g. 08:00-16:00".to_owned());
+        };
+        *pos += 3;
+        let to = if to <= from { to + MINUTES_PER_DAY } else { to };
+        spans.push((from, to));
+
+        match (tokens.get(*pos), tokens.get(*pos + 1)) {
+            (Some(Token::Comma), Some(Token::Time(_))) => *pos += 1,
+            _ => break,
+        }
+    }
+
+    let off = match tokens.get(*pos) {
+        Some(Token::Off) => {
+            *pos += 1;
+            true
+        }
+        Some(Token::Open) => {
+            *pos += 1;
+            false
+        }
+        _ => false,
+    };
+
+    if *pos == start {
+        return Err(format!("Unexpected {:?}", tokens.get(*pos)));
+    }
+
+    let spans = if off {
+        Vec::new()
+    } else if spans.is_empty() {
+        vec![(0, MINUTES_PER_DAY)]
+    } else {
+        spans
+    };
+
+    Ok(Rule {
+        days,
+        spans,
+        additive,
+    })
+}
+
+/// Converts Norwegian local time to UTC.
+/// Times skipped when moving the clock forward are moved forward as well
+fn to_utc(local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Oslo.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            Oslo.from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Norwegian public holidays, i.e. the official "helligdager" and the 1st and 17th of May
+pub fn is_public_holiday(date: NaiveDate) -> bool {
+    let fixed = matches!(
+        (date.month(), date.day()),
+        (1, 1) | (5, 1) | (5, 17) | (12, 25) | (12, 26)
+    );
+
+    // Maundy Thursday, Good Friday, Easter Sunday and Monday, Ascension Day, Whit Sunday and Monday
+    let from_easter = (date - easter_sunday(date.year())).num_days();
+    let movable = matches!(from_easter, -3 | -2 | 0 | 1 | 39 | 49 | 50);
+
+    fixed || movable
+}
+
+/// Easter Sunday in the Gregorian calendar, using the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("Easter is always a valid date")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A point in time given in Norwegian local time
+    fn oslo(date: &str, time: &str) -> DateTime<Utc> {
+        let local = NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M")
+            .expect("valid test time");
+        Oslo.from_local_datetime(&local)
+            .single()
+            .expect("unambiguous test time")
+            .with_timezone(&Utc)
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().expect("valid test timestamp")
+    }
+
+    fn parse(s: &str) -> OpeningHours {
+        s.parse()
+            .unwrap_or_else(|e| panic!("{s} should parse: {e}"))
+    }
+
+    #[test]
+    fn is_open() {
+        // 2024-03-11 is a Monday
+        let cases = [
+            ("24/7", "2024-03-11", "03:00", true),
+            ("24/7", "2024-12-25", "23:59", true),
+            ("Mo-Fr 08:00-16:00", "2024-03-13", "10:00", true),
+            ("Mo-Fr 08:00-16:00", "2024-03-13", "08:00", true),
+            ("Mo-Fr 08:00-16:00", "2024-03-13", "07:59", false),
+            ("Mo-Fr 08:00-16:00", "2024-03-13", "16:00", false),
+            ("Mo-Fr 08:00-16:00", "2024-03-16", "10:00", false),
+            // Days without times are open all day
+            ("Mo-Fr", "2024-03-13", "23:30", true),
+            ("Mo-Fr", "2024-03-16", "10:00", false),
+            (
+                "Mo-Fr 08:00-12:00,13:00-16:00",
+                "2024-03-13",
+                "12:30",
+                false,
+            ),
+            ("Mo-Fr 08:00-12:00,13:00-16:00", "2024-03-13", "13:30", true),
+            (
+                "Mo-Fr 08:00-16:00; Sa 10:00-14:00",
+                "2024-03-16",
+                "11:00",
+                true,
+            ),
+            ("Mo-Su 08:00-16:00; We off", "2024-03-13", "10:00", false),
+            ("Mo-Su 08:00-16:00; We closed", "2024-03-14", "10:00", true),
+            // Wraps around the end of the week
+            ("Sa-Mo 10:00-12:00", "2024-03-11", "11:00", true),
+            ("Sa-Mo 10:00-12:00", "2024-03-12", "11:00", false),
+            // Public holidays
+            ("Mo-Fr 08:00-16:00; PH off", "2024-05-17", "10:00", false),
+            ("Mo-Fr 08:00-16:00; PH off", "2024-03-28", "10:00", false),
+            ("Mo-Fr 08:00-16:00; PH off", "2024-05-16", "10:00", true),
+            (
+                "Mo-Fr 08:00-16:00; PH 10:00-14:00",
+                "2024-05-17",
+                "09:00",
+                false,
+            ),
+            (
+                "Mo-Fr 08:00-16:00; PH 10:00-14:00",
+                "2024-05-17",
+                "11:00",
+                true,
+            ),
+            ("Sa,Su,PH 10:00-14:00", "2024-04-01", "11:00", true),
+            // Spans past midnight continue into the next day
+            ("Fr-Sa 22:00-02:00", "2024-03-15", "23:00", true),
+            ("Fr-Sa 22:00-02:00", "2024-03-16", "01:00", true),
+            ("Fr-Sa 22:00-02:00", "2024-03-16", "03:00", false),
+            ("Fr-Sa 22:00-02:00", "2024-03-17", "01:00", true),
+            ("Fr-Sa 22:00-02:00", "2024-03-18", "01:00", false),
+            ("Mo-Fr 20:00-26:00", "2024-03-12", "01:30", true),
+        ];
+
+        for (hours, date, time, expected) in cases {
+            assert_eq!(
+                parse(hours).is_open(oslo(date, time)),
+                expected,
+                "{hours} at {date} {time}"
+            );
+        }
+    }
+
+    #[test]
+    fn next_change() {
+        let cases = [
+            ("24/7", "2024-03-13", "10:00", None),
+            ("off", "2024-03-13", "10:00", None),
+            (
+                "Mo-Fr 08:00-16:00",
+                "2024-03-13",
+                "10:00",
+                Some(oslo("2024-03-13", "16:00")),
+            ),
+            (
+                "Mo-Fr 08:00-16:00",
+                "2024-03-13",
+                "07:00",
+                Some(oslo("2024-03-13", "08:00")),
+            ),
+            // Across the weekend, into the next week
+            (
+                "Mo-Fr 08:00-16:00",
+                "2024-03-15",
+                "17:00",
+                Some(oslo("2024-03-18", "08:00")),
+            ),
+            (
+                "Mo-Fr 08:00-16:00",
+                "2024-03-17",
+                "12:00",
+                Some(oslo("2024-03-18", "08:00")),
+            ),
+            // Only open once a week
+            (
+                "Tu 10:00-12:00",
+                "2024-03-13",
+                "10:00",
+                Some(oslo("2024-03-19", "10:00")),
+            ),
+            // Skips Constitution Day on Friday and Whit Monday
+            (
+                "Mo-Fr 08:00-16:00; PH off",
+                "2024-05-16",
+                "17:00",
+                Some(oslo("2024-05-21", "08:00")),
+            ),
+            // Closes at the end of a span past midnight
+            (
+                "Fr-Sa 22:00-02:00",
+                "2024-03-15",
+                "23:00",
+                Some(oslo("2024-03-16", "02:00")),
+            ),
+            // Adjacent spans are merged
+            (
+                "Mo-Su 00:00-24:00; Tu 10:00-12:00",
+                "2024-03-12",
+                "11:00",
+                Some(oslo("2024-03-12", "12:00")),
+            ),
+        ];
+
+        for (hours, date, time, expected) in cases {
+            assert_eq!(
+                parse(hours).next_change(oslo(date, time)),
+                expected,
+                "{hours} at {date} {time}"
+            );
+        }
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        // Clocks go from 02:00 to 03:00 on 2024-03-31, and from 03:00 to 02:00 on 2024-10-27
+        let hours = parse("Mo-Su 08:00-16:00");
+        assert_eq!(
+            hours.next_change(oslo("2024-03-30", "17:00")),
+            Some(utc("2024-03-31T06:00:00Z"))
+        );
+        assert_eq!(
+            hours.next_change(oslo("2024-10-26", "17:00")),
+            Some(utc("2024-10-27T07:00:00Z"))
+        );
+        assert!(hours.is_open(utc("2024-03-31T06:30:00Z")));
+        assert!(!hours.is_open(utc("2024-10-27T06:30:00Z")));
+
+        // Opening in the skipped hour moves the opening forward as well
+        assert_eq!(
+            parse("Su 02:30-04:00").next_change(oslo("2024-03-30", "12:00")),
+            Some(utc("2024-03-31T01:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn easter_sunday_dates() {
+        let cases = [
+            (2000, "2000-04-23"),
+            (2019, "2019-04-21"),
+            (2024, "2024-03-31"),
+            (2025, "2025-04-20"),
+            (2026, "2026-04-05"),
+            (2038, "2038-04-25"),
+        ];
+
+        for (year, expected) in cases {
+            assert_eq!(
+                easter_sunday(year),
+                expected.parse::<NaiveDate>().unwrap(),
+                "{year}"
+            );
+        }
+    }
+
+    #[test]
+    fn public_holidays() {
+        let cases = [
+            ("2025-01-01", true),
+            ("2025-04-16", false),
+            ("2025-04-17", true),
+            ("2025-04-18", true),
+            ("2025-04-19", false),
+            ("2025-04-20", true),
+            ("2025-04-21", true),
+            ("2025-05-01", true),
+            ("2025-05-17", true),
+            ("2025-05-29", true),
+            ("2025-06-08", true),
+            ("2025-06-09", true),
+            ("2025-06-10", false),
+            ("2025-12-24", false),
+            ("2025-12-25", true),
+            ("2025-12-26", true),
+            ("2026-04-02", true),
+            ("2026-05-14", true),
+            ("2026-05-25", true),
+        ];
+
+        for (date, expected) in cases {
+            assert_eq!(is_public_holiday(date.parse().unwrap()), expected, "{date}");
+        }
+    }
+
+    #[test]
+    fn invalid_opening_hours() {
+        let cases = [
+            "",
+            "Mo-Fr 8-16",
+            "Mo-Fr 08:00",
+            "Mo-Fr 08:00-16:61",
+            "Mo- 08:00-16:00",
+            "Xy 08:00-16:00",
+            "Mo-Fr 08:00-16:00 Sa",
+            "sunrise-sunset",
+        ];
+
+        for hours in cases {
+            assert!(
+                hours.parse::<OpeningHours>().is_err(),
+                "{hours:?} should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use mongodb::{
+    Database,
+    bson::{Bson, Document, Uuid},
+};
+use serde_json::{Map, Value};
+
+use crate::auth::User;
+use crate::models::{ChangingRoom, RoomChange};
+use crate::revision::{etag, write_room};
+use crate::update_room::UpdateChangingRoom;
+
+/// Fields of `UpdateChangingRoom` which can be changed with a patch
+const PATCHABLE_FIELDS: [&str; 4] = ["name", "externalId", "location", "attributes"];
+
+/// Partially updates a room using JSON Merge Patch (RFC 7386) semantics
+pub async fn patch_room(
+    user: User,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    State(db): State<Database>,
+    Json(patch): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let Value::Object(patch_fields) = &patch else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Patch must be a JSON object".to_owned(),
+        ));
+    };
+
+    if let Some(field) = patch_fields
+        .keys()
+        .find(|f| !PATCHABLE_FIELDS.contains(&f.as_str()))
+    {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Field {field} can not be patched. Allowed fields are {PATCHABLE_FIELDS:?}"),
+        ));
+    }
+
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Patching room"
+    );
+
+    // The patch is merged with the current room, and merged again if another write gets there first
+    let room = write_room(&db, id, &headers, &user, RoomChange::Update, |existing| {
+        if patch_fields.is_empty() {
+            return Ok(None);
+        }
+        merged_fields(existing, &patch, patch_fields).map(Some)
+    })
+    .await?;
+
+    Ok(([(header::ETAG, etag(room.revision))], Json(room)))
+}
+
+/// The fields to `$set` for applying `patch` to `existing`
+fn merged_fields(
+    existing: &ChangingRoom,
+    patch: &Value,
+    patch_fields: &Map<String, Value>,
+) -> Result<Document, (StatusCode, String)> {
+    let mut merged =
+        serde_json::to_value(UpdateChangingRoom::from(existing.clone())).map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to serialize room for patching");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured updating changing room".to_owned(),
+            )
+        })?;
+    json_patch::merge(&mut merged, patch);
+
+    // Validate the result the same way as a full update
+    let payload = serde_json::from_value::<UpdateChangingRoom>(merged).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Invalid room after applying patch");
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid room after applying patch: {e}"),
+        )
+    })?;
+
+    let patched = payload.to_set_document().map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize patched room");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured updating changing room".to_owned(),
+        )
+    })?;
+
+    // Only set the patched fields, so that the update doesn't touch anything else
+    let mut set = Document::new();
+    for field in patch_fields.keys() {
+        set.insert(field, patched.get(field).cloned().unwrap_or(Bson::Null));
+    }
+    if patch_fields.contains_key("location") {
+        set.insert(
+            "locationGeo",
+            patched.get("locationGeo").cloned().unwrap_or(Bson::Null),
+        );
+    }
+
+    Ok(set)
+}
@@ -0,0 +1,279 @@
+use std::{env, sync::LazyLock};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    Database,
+    bson::{self, Uuid, doc},
+    options::ReturnDocument,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::User;
+use crate::models::ChangingRoom;
+
+pub const REPORTS_COLLECTION: &str = "reports";
+
+/// Rooms are hidden when this many users have open reports about them
+pub static REPORT_THRESHOLD: LazyLock<u64> = LazyLock::new(|| {
+    env::var("ROOM_API_REPORT_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3)
+});
+
+/// A user report about something wrong with a room, stored in the `reports` collection
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomReport {
+    pub id: Uuid,
+    #[serde(rename = "roomId")]
+    pub room_id: Uuid,
+    pub reason: RoomReportReason,
+    pub comment: Option<String>,
+    #[serde(rename = "reportedBy")]
+    pub reported_by: String,
+    #[serde(rename = "reportedAt")]
+    pub reported_at: DateTime<Utc>,
+    /// Set when an admin has looked at the report. None means the report is open
+    #[serde(default)]
+    pub resolved: Option<ReportResolution>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomReportReason {
+    Nonexistent,
+    Closed,
+    WrongLocation,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReportResolution {
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: DateTime<Utc>,
+    #[serde(rename = "resolvedBy")]
+    pub resolved_by: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreateRoomReport {
+    pub reason: RoomReportReason,
+    pub comment: Option<String>,
+}
+
+pub async fn report_room(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    Json(payload): Json<CreateRoomReport>,
+) -> Result<(StatusCode, Json<RoomReport>), (StatusCode, String)> {
+    let id = parse_id(&id)?;
+
+    let rooms = db.collection::<ChangingRoom>("rooms");
+    let reports = db.collection::<RoomReport>(REPORTS_COLLECTION);
+
+    let room = rooms
+        .find_one(doc! { "id": id })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get room by id from db");
+            report_error()
+        })?
+        .ok_or((StatusCode::NOT_FOUND, format!("No room found with id {id}")))?;
+
+    if room.deleted.is_some() {
+        return Err((
+            StatusCode::GONE,
+            format!("Room with id {id} has been deleted"),
+        ));
+    }
+
+    // Each user counts once towards hiding a room
+    let already_reported = reports
+        .find_one(doc! { "roomId": id, "reportedBy": &user.subject, "resolved": null })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get reports from db");
+            report_error()
+        })?;
+    if already_reported.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("You have already reported room {id}"),
+        ));
+    }
+
+    let report = RoomReport {
+        id: Uuid::new(),
+        room_id: id,
+        reason: payload.reason,
+        comment: payload.comment,
+        reported_by: user.subject.clone(),
+        reported_at: Utc::now(),
+        resolved: None,
+    };
+
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Reporting room as {:?}",
+        report.reason
+    );
+
+    reports.insert_one(&report).await.map_err(|e| {
+        tracing::error!(err = e.to_string(), "Error persisting report to db");
+        report_error()
+    })?;
+
+    let open_reports = reports
+        .count_documents(doc! { "roomId": id, "resolved": null })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to count reports for room");
+            report_error()
+        })?;
+
+    if open_reports >= *REPORT_THRESHOLD && !room.hidden {
+        tracing::warn!(
+            room_id = id.to_string(),
+            open_reports,
+            "Hiding room until reports have been resolved"
+        );
+        rooms
+            .update_one(doc! { "id": id }, doc! { "$set": { "hidden": true } })
+            .await
+            .map_err(|e| {
+                tracing::error!(err = e.to_string(), "Unable to hide reported room");
+                report_error()
+            })?;
+    }
+
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Reports which haven't been resolved yet, oldest first
+pub async fn get_open_reports(
+    user: User,
+    State(db): State<Database>,
+) -> Result<Json<Vec<RoomReport>>, (StatusCode, String)> {
+    require_admin(&user)?;
+
+    let reports = db
+        .collection::<RoomReport>(REPORTS_COLLECTION)
+        .find(doc! { "resolved": null })
+        .sort(doc! { "reportedAt": 1 })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get cursor for reports");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reports from database".to_owned(),
+            )
+        })?
+        .try_collect()
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to collect reports into Vec");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting reports from database".to_owned(),
+            )
+        })?;
+
+    Ok(Json(reports))
+}
+
+/// Marks the open reports about a room as resolved, and shows the room again if it was hidden.
+///
+/// Rooms which really are gone should be deleted before resolving the reports.
+pub async fn resolve_reports(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<Json<ChangingRoom>, (StatusCode, String)> {
+    require_admin(&user)?;
+    let id = parse_id(&id)?;
+
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Resolving reports about room"
+    );
+
+    let resolution = bson::to_bson(&ReportResolution {
+        resolved_at: Utc::now(),
+        resolved_by: user.subject.clone(),
+    })
+    .map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize report resolution");
+        resolve_error()
+    })?;
+
+    let room = db
+        .collection::<ChangingRoom>("rooms")
+        .find_one_and_update(doc! { "id": id }, doc! { "$set": { "hidden": false } })
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to unhide room");
+            resolve_error()
+        })?
+        .ok_or((StatusCode::NOT_FOUND, format!("No room found with id {id}")))?;
+
+    db.collection::<RoomReport>(REPORTS_COLLECTION)
+        .update_many(
+            doc! { "roomId": id, "resolved": null },
+            doc! { "$set": { "resolved": resolution } },
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to resolve reports");
+            resolve_error()
+        })?;
+
+    Ok(Json(room))
+}
+
+fn parse_id(id: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })
+}
+
+fn require_admin(user: &User) -> Result<(), (StatusCode, String)> {
+    if user.is_admin {
+        Ok(())
+    } else {
+        tracing::warn!(
+            user = user.subject,
+            "Non-admin user attempted to handle reports"
+        );
+        Err((
+            StatusCode::FORBIDDEN,
+            "Only admins are allowed to handle reports".to_owned(),
+        ))
+    }
+}
+
+fn report_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "An unexpected error occured trying to report room".to_owned(),
+    )
+}
+
+fn resolve_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "An unexpected error occured trying to resolve reports".to_owned(),
+    )
+}
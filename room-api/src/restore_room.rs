@@ -0,0 +1,106 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use mongodb::{
+    Database,
+    bson::{Uuid, doc},
+    options::ReturnDocument,
+};
+
+use crate::auth::User;
+use crate::history::{record_change, start_transaction};
+use crate::models::{ChangingRoom, RoomChange};
+
+pub async fn restore_room(
+    user: User,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+) -> Result<Json<ChangingRoom>, (StatusCode, String)> {
+    if !user.is_admin {
+        tracing::warn!(
+            user = user.subject,
+            "Non-admin user attempted to restore room"
+        );
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only admins are allowed to restore rooms".to_owned(),
+        ));
+    }
+
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Restoring room"
+    );
+
+    let collection = db.collection::<ChangingRoom>("rooms");
+
+    let restore_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to restore room");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured restoring changing room".to_owned(),
+        )
+    };
+
+    let mut session = start_transaction(&db).await.map_err(restore_error)?;
+
+    let restored = collection
+        .find_one_and_update(
+            doc! { "id": id, "deleted": { "$ne": null } },
+            doc! { "$set": { "deleted": null }, "$inc": { "revision": 1 } },
+        )
+        .return_document(ReturnDocument::Before)
+        .session(&mut session)
+        .await
+        .map_err(restore_error)?;
+
+    if let Some(before) = restored {
+        // The update is atomic, so the restored room follows from the room before it
+        let room = ChangingRoom {
+            revision: before.revision + 1,
+            deleted: None,
+            ..before.clone()
+        };
+        record_change(
+            &db,
+            &mut session,
+            RoomChange::Restore,
+            &user,
+            Some(before),
+            room.clone(),
+        )
+        .await
+        .map_err(restore_error)?;
+        session.commit_transaction().await.map_err(restore_error)?;
+        return Ok(Json(room));
+    }
+
+    session.abort_transaction().await.map_err(restore_error)?;
+
+    let existing = collection.find_one(doc! { "id": id }).await.map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to get room by id from db");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured restoring changing room".to_owned(),
+        )
+    })?;
+
+    match existing {
+        Some(_) => Err((
+            StatusCode::CONFLICT,
+            format!("Room with id {id} is not deleted"),
+        )),
+        None => Err((StatusCode::NOT_FOUND, format!("No room found with id {id}"))),
+    }
+}
@@ -0,0 +1,180 @@
+//! Optimistic concurrency for rooms, using the room revision as ETag
+
+use axum::http::{HeaderMap, StatusCode, header};
+use mongodb::{
+    Collection, Database,
+    bson::{Document, Uuid, doc},
+    error::TRANSIENT_TRANSACTION_ERROR,
+    options::ReturnDocument,
+};
+
+use crate::auth::User;
+use crate::history::{record_change, start_transaction};
+use crate::models::{ChangingRoom, RoomChange};
+
+/// How many times a write is retried after losing a race with another write to the same room
+const MAX_WRITE_ATTEMPTS: u32 = 5;
+
+pub fn etag(revision: i64) -> String {
+    format!("\"{revision}\"")
+}
+
+/// Revisions the client accepts the room to be at, taken from the If-Match header.
+///
+/// None if there is no If-Match header or it is `*`, meaning any revision is fine.
+/// Weak or malformed entity tags never match.
+fn if_match_revisions(headers: &HeaderMap) -> Option<Vec<i64>> {
+    if !headers.contains_key(header::IF_MATCH) {
+        return None;
+    }
+
+    let mut revisions = Vec::new();
+    for value in headers.get_all(header::IF_MATCH) {
+        for tag in value.to_str().unwrap_or_default().split(',').map(str::trim) {
+            if tag == "*" {
+                return None;
+            }
+            if let Some(revision) = tag
+                .strip_prefix('"')
+                .and_then(|t| t.strip_suffix('"'))
+                .and_then(|t| t.parse::<i64>().ok())
+            {
+                revisions.push(revision);
+            }
+        }
+    }
+    Some(revisions)
+}
+
+/// Adds a condition on the room revision to `filter`, if the client sent If-Match
+fn with_revisions(mut filter: Document, revisions: &Option<Vec<i64>>) -> Document {
+    if let Some(revisions) = revisions {
+        filter.insert("revision", doc! { "$in": revisions });
+    }
+    filter
+}
+
+/// Read-modify-write of the active room with `id`, recorded in the room history in the same transaction.
+///
+/// `change` gets the current room and returns the fields to `$set`, or None to leave the room as is.
+/// The fields are only set if the room is still at the revision `change` saw.
+/// When another write gets there first, the room is read again and `change` retried.
+/// If the client sent If-Match, the new revision doesn't match it, so that fails with 412 Precondition Failed.
+pub async fn write_room(
+    db: &Database,
+    id: Uuid,
+    headers: &HeaderMap,
+    user: &User,
+    action: RoomChange,
+    mut change: impl FnMut(&ChangingRoom) -> Result<Option<Document>, (StatusCode, String)>,
+) -> Result<ChangingRoom, (StatusCode, String)> {
+    let collection = db.collection::<ChangingRoom>("rooms");
+    let revisions = if_match_revisions(headers);
+
+    let write_error = |e: mongodb::error::Error| {
+        tracing::error!(err = e.to_string(), "Unable to write room to db");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured writing changing room to database".to_owned(),
+        )
+    };
+
+    for _ in 0..MAX_WRITE_ATTEMPTS {
+        let existing = collection
+            .find_one(with_revisions(
+                doc! { "id": id, "deleted": null },
+                &revisions,
+            ))
+            .await
+            .map_err(write_error)?;
+
+        let Some(existing) = existing else {
+            return Err(unmatched_write_error(&collection, id).await);
+        };
+
+        let Some(set) = change(&existing)? else {
+            return Ok(existing);
+        };
+
+        match write_revision(db, &collection, user, action, existing, set).await {
+            Ok(Some(room)) => return Ok(room),
+            Ok(None) => {}
+            Err(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR) => {}
+            Err(e) => return Err(write_error(e)),
+        }
+
+        tracing::info!(
+            room_id = id.to_string(),
+            "Room was changed by another request. Retrying"
+        );
+    }
+
+    Err((
+        StatusCode::CONFLICT,
+        format!("Room with id {id} is being changed by others. Please try again"),
+    ))
+}
+
+/// Sets `set` on the room if it's still at the revision of `existing`, and records the change.
+///
+/// None if the room has been changed since `existing` was read, in which case the transaction is aborted.
+async fn write_revision(
+    db: &Database,
+    collection: &Collection<ChangingRoom>,
+    user: &User,
+    action: RoomChange,
+    existing: ChangingRoom,
+    set: Document,
+) -> Result<Option<ChangingRoom>, mongodb::error::Error> {
+    let mut session = start_transaction(db).await?;
+
+    let room = collection
+        .find_one_and_update(
+            doc! { "id": existing.id, "revision": existing.revision },
+            doc! { "$set": set, "$inc": { "revision": 1 } },
+        )
+        .return_document(ReturnDocument::After)
+        .session(&mut session)
+        .await?;
+
+    let Some(room) = room else {
+        session.abort_transaction().await?;
+        return Ok(None);
+    };
+
+    record_change(db, &mut session, action, user, Some(existing), room.clone()).await?;
+    session.commit_transaction().await?;
+
+    Ok(Some(room))
+}
+
+/// Error for when a write targeting an active room with `id` matched nothing.
+///
+/// Tells apart rooms that don't exist, rooms that have been deleted,
+/// and rooms which have been modified since the revision given in If-Match.
+async fn unmatched_write_error(
+    collection: &Collection<ChangingRoom>,
+    id: Uuid,
+) -> (StatusCode, String) {
+    match collection.find_one(doc! { "id": id }).await {
+        Ok(Some(room)) if room.deleted.is_some() => (
+            StatusCode::GONE,
+            format!("Room with id {id} has been deleted"),
+        ),
+        Ok(Some(room)) => (
+            StatusCode::PRECONDITION_FAILED,
+            format!(
+                "Room with id {id} has been modified. Current revision is {}",
+                room.revision
+            ),
+        ),
+        Ok(None) => (StatusCode::NOT_FOUND, format!("No room found with id {id}")),
+        Err(e) => {
+            tracing::error!(err = e.to_string(), "Unable to get room by id from db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured getting data from database".to_owned(),
+            )
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use mongodb::{
+    Database,
+    bson::{Uuid, doc},
+};
+use serde::Deserialize;
+
+use crate::auth::User;
+use crate::history::HISTORY_COLLECTION;
+use crate::models::{RoomChange, RoomHistoryEntry};
+use crate::revision::{etag, write_room};
+use crate::update_room::UpdateChangingRoom;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RollbackRoom {
+    /// Revision to roll back to
+    pub revision: i64,
+}
+
+/// Sets a room back to how it was at an earlier revision, except for the ratings.
+///
+/// Rolling back creates a new revision, so the rollback itself can be undone.
+pub async fn rollback_room(
+    user: User,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    State(db): State<Database>,
+    Json(payload): Json<RollbackRoom>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        revision = payload.revision,
+        "Rolling back room"
+    );
+
+    let target = db
+        .collection::<RoomHistoryEntry>(HISTORY_COLLECTION)
+        .find_one(doc! { "roomId": id, "revision": payload.revision })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to get room history from db");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured rolling back changing room".to_owned(),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!(
+                "No revision {} found in the history of room {id}",
+                payload.revision
+            ),
+        ))?
+        .after;
+
+    // Ratings aren't part of the update, as they are computed from reviews and kept as is
+    let set = UpdateChangingRoom::from(target)
+        .to_set_document()
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to serialize room rollback");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured rolling back changing room".to_owned(),
+            )
+        })?;
+
+    let room = write_room(&db, id, &headers, &user, RoomChange::Rollback, |existing| {
+        if payload.revision >= existing.revision {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "Can only roll back to revisions before the current revision {}",
+                    existing.revision
+                ),
+            ));
+        }
+        Ok(Some(set.clone()))
+    })
+    .await?;
+
+    Ok(([(header::ETAG, etag(room.revision))], Json(room)))
+}
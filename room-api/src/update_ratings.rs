@@ -0,0 +1,58 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use mongodb::{
+    Database,
+    bson::{self, Uuid, doc},
+};
+
+use crate::auth::Service;
+use crate::models::{ChangingRoom, Ratings};
+
+/// Sets the ratings of a room, as computed by review-api. `null` removes them.
+///
+/// Only review-api sets ratings, as `PUT` and `PATCH` of a room leave them unchanged.
+/// Ratings are derived from reviews, so the revision is left as is and no history is recorded.
+pub async fn update_room_ratings(
+    _service: Service,
+    Path(id): Path<String>,
+    State(db): State<Database>,
+    Json(ratings): Json<Option<Ratings>>,
+) -> Result<(), (StatusCode, String)> {
+    let id = Uuid::parse_str(&id).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Id {id} is incorrectly formatted. Must be a valid uuid."),
+        )
+    })?;
+
+    let ratings = bson::to_bson(&ratings).map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize ratings");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured trying to update ratings".to_owned(),
+        )
+    })?;
+
+    let res = db
+        .collection::<ChangingRoom>("rooms")
+        .update_one(doc! { "id": id }, doc! { "$set": { "ratings": ratings } })
+        .await
+        .map_err(|e| {
+            tracing::error!(err = e.to_string(), "Unable to update ratings of room");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An unexpected error occured trying to update ratings".to_owned(),
+            )
+        })?;
+
+    if res.matched_count == 0 {
+        return Err((StatusCode::NOT_FOUND, format!("No room found with id {id}")));
+    }
+
+    tracing::info!(room_id = id.to_string(), "Updated ratings of room");
+    Ok(())
+}
@@ -1,31 +1,64 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
     Json,
 };
 use geojson::{Geometry, Value};
 use mongodb::{
-    bson::{doc, Uuid},
+    bson::{self, doc, Document, Uuid},
     Database,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::models::{ChangingRoom, Location, Ratings};
+use crate::auth::User;
+use crate::models::{Attributes, ChangingRoom, Location, RoomChange};
+use crate::revision::{etag, write_room};
 
-#[derive(Clone, Debug, Deserialize)]
+/// The fields of a room which users can change. Ratings are only set by review-api
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UpdateChangingRoom {
     pub name: String,
     #[serde(rename = "externalId")]
     pub external_id: Option<String>,
     pub location: Location,
-    pub ratings: Option<Ratings>,
+    #[serde(default)]
+    pub attributes: Attributes,
+}
+
+impl UpdateChangingRoom {
+    /// The room fields as a `$set` document, including `locationGeo` computed from `location`
+    pub fn to_set_document(&self) -> Result<Document, bson::ser::Error> {
+        let mut set = bson::to_document(self)?;
+        set.insert(
+            "locationGeo",
+            bson::to_bson(&Geometry::new(Value::Point(vec![
+                self.location.lng,
+                self.location.lat,
+            ])))?,
+        );
+        Ok(set)
+    }
+}
+
+impl From<ChangingRoom> for UpdateChangingRoom {
+    fn from(room: ChangingRoom) -> Self {
+        UpdateChangingRoom {
+            name: room.name,
+            external_id: room.external_id,
+            location: room.location,
+            attributes: room.attributes,
+        }
+    }
 }
 
 pub async fn update_room(
+    user: User,
     Path(id): Path<String>,
+    headers: HeaderMap,
     State(db): State<Database>,
     Json(payload): Json<UpdateChangingRoom>,
-) -> Result<Json<ChangingRoom>, (StatusCode, String)> {
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     let id = Uuid::parse_str(&id).map_err(|e| {
         tracing::error!(err = e.to_string(), "Unable to parse uuid from string");
         (
@@ -34,34 +67,24 @@ pub async fn update_room(
         )
     })?;
 
-    let collection = db.collection::<ChangingRoom>("rooms");
+    tracing::info!(
+        room_id = id.to_string(),
+        user = user.subject,
+        "Updating room"
+    );
 
-    let updated_room = collection
-        .find_one_and_replace(
-            doc! { "id": id },
-            ChangingRoom {
-                id,
-                external_id: payload.external_id,
-                name: payload.name,
-                location: payload.location,
-                location_geo: Geometry::new(Value::Point(vec![
-                    payload.location.lng,
-                    payload.location.lat,
-                ])),
-                ratings: payload.ratings,
-            },
+    let set = payload.to_set_document().map_err(|e| {
+        tracing::error!(err = e.to_string(), "Unable to serialize room update");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "An unexpected error occured updating changing room".to_owned(),
         )
-        .await
-        .map_err(|e| {
-            tracing::error!(err = e.to_string(), "Unable to find and replace room");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "An unexpected error occured updating changing room".to_owned(),
-            )
-        })?;
+    })?;
 
-    match updated_room {
-        Some(room) => Ok(Json(room)),
-        None => Err((StatusCode::NOT_FOUND, format!("No room found with id {id}"))),
-    }
+    let room = write_room(&db, id, &headers, &user, RoomChange::Update, |_| {
+        Ok(Some(set.clone()))
+    })
+    .await?;
+
+    Ok(([(header::ETAG, etag(room.revision))], Json(room)))
 }